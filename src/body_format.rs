@@ -0,0 +1,246 @@
+use actix_web::HttpRequest;
+use actix_web::http::header;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::{json, Map, Value as JsonValue};
+
+pub const FORM_URLENCODED: &str = "application/x-www-form-urlencoded";
+pub const MULTIPART_FORM_DATA: &str = "multipart/form-data";
+pub const APPLICATION_XML: &str = "application/xml";
+pub const TEXT_XML: &str = "text/xml";
+pub const APPLICATION_OCTET_STREAM: &str = "application/octet-stream";
+
+/// Media type of a request without parameters, lowercased (e.g. "application/json")
+pub fn media_type(req: &HttpRequest) -> String {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Full Content-Type header of a request, parameters included
+fn content_type_header(req: &HttpRequest) -> &str {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Parse a body into JSON using the parser its Content-Type calls for.
+/// Form, multipart and XML bodies become objects, anything else must be JSON.
+pub fn parse_body(req: &HttpRequest, body: &[u8]) -> Result<JsonValue, String> {
+    match media_type(req).as_str() {
+        FORM_URLENCODED => form_to_json(body).map_err(|e| e.to_string()),
+        MULTIPART_FORM_DATA => multipart_to_json(req, body),
+        APPLICATION_XML | TEXT_XML => xml_to_json(body),
+        _ => serde_json::from_slice(body).map_err(|e| e.to_string()),
+    }
+}
+
+/// Whether `parse_body` converts this request's body to JSON, rather than reading it as JSON
+pub fn converts_to_json(req: &HttpRequest) -> bool {
+    matches!(media_type(req).as_str(), FORM_URLENCODED | MULTIPART_FORM_DATA | APPLICATION_XML | TEXT_XML)
+}
+
+/// Decode a form-urlencoded body into its key/value pairs, in body order
+pub fn parse_form_pairs(body: &[u8]) -> Result<Vec<(String, String)>, serde_urlencoded::de::Error> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+}
+
+/// Convert a form-urlencoded body into a JSON object. Repeated keys become arrays.
+pub fn form_to_json(body: &[u8]) -> Result<JsonValue, serde_urlencoded::de::Error> {
+    let mut object = Map::new();
+
+    for (key, value) in parse_form_pairs(body)? {
+        insert_field(&mut object, key, JsonValue::String(value));
+    }
+
+    Ok(JsonValue::Object(object))
+}
+
+/// Insert a field, turning repeated names into an array of values
+fn insert_field(object: &mut Map<String, JsonValue>, key: String, value: JsonValue) {
+    match object.get_mut(&key) {
+        Some(JsonValue::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = JsonValue::Array(vec![first, value]);
+        }
+        None => {
+            object.insert(key, value);
+        }
+    }
+}
+
+/// Convert a multipart/form-data body into a JSON object.
+///
+/// Text fields become strings. File parts become `{"filename", "content_type", "data"}`
+/// with the content base64-encoded. Repeated names become arrays.
+pub fn multipart_to_json(req: &HttpRequest, body: &[u8]) -> Result<JsonValue, String> {
+    let boundary = content_type_header(req)
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|b| b.trim_matches('"').to_string())
+        .next()
+        .ok_or("Multipart boundary missing from Content-Type")?;
+
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let part_end = [b"\r\n".as_slice(), &delimiter].concat();
+
+    let mut pos = find_bytes(body, &delimiter, 0).ok_or("Multipart delimiter not found")? + delimiter.len();
+    let mut object = Map::new();
+
+    // The last delimiter is followed by "--"
+    while !body[pos..].starts_with(b"--") {
+        let start = pos + if body[pos..].starts_with(b"\r\n") { 2 } else { 0 };
+        let end = find_bytes(body, &part_end, start).ok_or("Unterminated multipart part")?;
+
+        let (name, value) = parse_multipart_part(&body[start..end])?;
+        insert_field(&mut object, name, value);
+
+        pos = end + part_end.len();
+    }
+
+    Ok(JsonValue::Object(object))
+}
+
+fn parse_multipart_part(part: &[u8]) -> Result<(String, JsonValue), String> {
+    let split = find_bytes(part, b"\r\n\r\n", 0).ok_or("Multipart part has no header terminator")?;
+    let headers = String::from_utf8_lossy(&part[..split]);
+    let content = &part[split + 4..];
+
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+
+    for line in headers.lines() {
+        let Some((header_name, header_value)) = line.split_once(':') else { continue };
+        match header_name.trim().to_lowercase().as_str() {
+            "content-disposition" => {
+                for param in header_value.split(';').skip(1) {
+                    match param.trim().split_once('=') {
+                        Some(("name", v)) => name = Some(v.trim_matches('"').to_string()),
+                        Some(("filename", v)) => filename = Some(v.trim_matches('"').to_string()),
+                        _ => {}
+                    }
+                }
+            }
+            "content-type" => content_type = Some(header_value.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    let name = name.ok_or("Multipart part has no field name")?;
+    let value = match filename {
+        Some(filename) => json!({
+            "filename": filename,
+            "content_type": content_type.unwrap_or_else(|| "application/octet-stream".to_string()),
+            "data": BASE64.encode(content),
+        }),
+        None => JsonValue::String(String::from_utf8_lossy(content).into_owned()),
+    };
+
+    Ok((name, value))
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| i + from)
+}
+
+/// Element being built while reading an XML document
+struct XmlNode {
+    name: String,
+    object: Map<String, JsonValue>,
+    text: String,
+}
+
+impl XmlNode {
+    fn new(start: &BytesStart) -> Result<Self, String> {
+        let mut object = Map::new();
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(|e| e.to_string())?;
+            let key = format!("@{}", String::from_utf8_lossy(attribute.key.as_ref()));
+            let value = attribute.unescape_value().map_err(|e| e.to_string())?;
+            object.insert(key, JsonValue::String(value.into_owned()));
+        }
+
+        Ok(Self {
+            name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+            object,
+            text: String::new(),
+        })
+    }
+
+    /// Text-only elements become strings, others objects with their text under `#text`
+    fn into_value(mut self) -> JsonValue {
+        if self.object.is_empty() {
+            return JsonValue::String(self.text);
+        }
+        if !self.text.is_empty() {
+            self.object.insert("#text".to_string(), JsonValue::String(self.text));
+        }
+        JsonValue::Object(self.object)
+    }
+}
+
+/// Convert an XML document into JSON keyed by element name, starting with the root element.
+///
+/// `<xml><ToUserName><![CDATA[gh_123]]></ToUserName><Id a="1">7</Id></xml>` becomes
+/// `{"xml": {"ToUserName": "gh_123", "Id": {"@a": "1", "#text": "7"}}}`. Attributes are
+/// prefixed with `@` and repeated elements become arrays.
+pub fn xml_to_json(body: &[u8]) -> Result<JsonValue, String> {
+    let mut reader = Reader::from_reader(body);
+    reader.config_mut().trim_text(true);
+
+    let mut stack: Vec<XmlNode> = Vec::new();
+    let mut root = Map::new();
+
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(start) => stack.push(XmlNode::new(&start)?),
+            Event::Empty(start) => {
+                let node = XmlNode::new(&start)?;
+                close_xml_node(&mut stack, &mut root, node);
+            }
+            Event::End(_) => {
+                let node = stack.pop().ok_or("Unbalanced XML end tag")?;
+                close_xml_node(&mut stack, &mut root, node);
+            }
+            Event::Text(text) => {
+                if let Some(node) = stack.last_mut() {
+                    node.text.push_str(&text.unescape().map_err(|e| e.to_string())?);
+                }
+            }
+            Event::CData(data) => {
+                if let Some(node) = stack.last_mut() {
+                    node.text.push_str(&String::from_utf8_lossy(&data.into_inner()));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !stack.is_empty() || root.is_empty() {
+        return Err("Incomplete XML document".to_string());
+    }
+
+    Ok(JsonValue::Object(root))
+}
+
+fn close_xml_node(stack: &mut [XmlNode], root: &mut Map<String, JsonValue>, node: XmlNode) {
+    let name = node.name.clone();
+    let value = node.into_value();
+    match stack.last_mut() {
+        Some(parent) => insert_field(&mut parent.object, name, value),
+        None => insert_field(root, name, value),
+    }
+}
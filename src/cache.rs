@@ -92,11 +92,11 @@ impl OrderedCache {
         let mut removed = Vec::new();
 
         while removed.len() < n && !order.is_empty() {
-            if let Some(key) = order.pop_front() {
-                if let Some(value) = cache.get(&key).await {
-                    removed.push((key.clone(), value));
-                    cache.invalidate(&key).await;
-                }
+            if let Some(key) = order.pop_front()
+                && let Some(value) = cache.get(&key).await
+            {
+                removed.push((key.clone(), value));
+                cache.invalidate(&key).await;
             }
         }
 
@@ -112,11 +112,11 @@ impl OrderedCache {
         let mut removed = Vec::new();
 
         while removed.len() < n && !order.is_empty() {
-            if let Some(key) = order.pop_back() {
-                if let Some(value) = cache.get(&key).await {
-                    removed.push((key.clone(), value));
-                    cache.invalidate(&key).await;
-                }
+            if let Some(key) = order.pop_back()
+                && let Some(value) = cache.get(&key).await
+            {
+                removed.push((key.clone(), value));
+                cache.invalidate(&key).await;
             }
         }
 
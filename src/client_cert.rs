@@ -0,0 +1,168 @@
+// client_cert.rs
+use std::any::Any;
+use std::env;
+use std::fs;
+use std::io::BufReader;
+use std::net::IpAddr;
+use actix_tls::accept::rustls_0_20::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use actix_web::Error;
+use log::{error, warn};
+use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth, WantsServerCert};
+use rustls::{ConfigBuilder, RootCertStore, ServerConfig};
+use serde::Deserialize;
+use x509_parser::extensions::GeneralName;
+
+pub mod test;
+
+/// Env var naming the CA bundle client certificates are verified against
+pub const CLIENT_CA_FILE_ENV: &str = "SSL_CLIENT_CA_FILE";
+
+/// Env var set to `required` to refuse TLS connections without a client certificate
+pub const CLIENT_AUTH_ENV: &str = "SSL_CLIENT_AUTH";
+
+/// Subject and alternative names of the verified certificate a client connected with
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerCertificate {
+    subject: String,
+    sans: Vec<String>,
+}
+
+impl PeerCertificate {
+    pub fn from_der(der: &[u8]) -> Result<Self, String> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| format!("Invalid client certificate: {}", e))?;
+
+        let sans = match cert.subject_alternative_name() {
+            Ok(Some(extension)) => extension.value.general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) | GeneralName::RFC822Name(name) | GeneralName::URI(name) => {
+                        Some(name.to_string())
+                    }
+                    GeneralName::IPAddress(bytes) => match bytes.len() {
+                        4 => <[u8; 4]>::try_from(*bytes).ok().map(|ip| IpAddr::from(ip).to_string()),
+                        16 => <[u8; 16]>::try_from(*bytes).ok().map(|ip| IpAddr::from(ip).to_string()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(Self { subject: cert.subject().to_string(), sans })
+    }
+
+    pub fn get_subject(&self) -> String {
+        self.subject.clone()
+    }
+
+    pub fn get_sans(&self) -> &[String] {
+        &self.sans
+    }
+}
+
+/// Client certificates a data alias accepts. With both lists empty any certificate
+/// signed by the configured CA is accepted.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ClientCertConfig {
+    #[serde(default)]
+    subjects: Vec<String>, // Full subjects, e.g. "O=Partner Inc, CN=client.partner.example"
+    #[serde(default)]
+    sans: Vec<String>, // DNS names, emails, URIs or IPs; "*.partner.example" matches one label
+}
+
+impl ClientCertConfig {
+    pub fn get_subjects(&self) -> &[String] {
+        &self.subjects
+    }
+
+    pub fn get_sans(&self) -> &[String] {
+        &self.sans
+    }
+
+    pub fn allows(&self, peer: &PeerCertificate) -> bool {
+        if self.subjects.is_empty() && self.sans.is_empty() {
+            return true;
+        }
+
+        self.subjects.iter().any(|subject| subject.eq_ignore_ascii_case(&peer.subject))
+            || self.sans.iter().any(|allowed| peer.get_sans().iter().any(|san| san_matches(allowed, san)))
+    }
+
+    /// Require a verified client certificate that is on the allowlist
+    pub fn check(&self, peer: Option<&PeerCertificate>, alias: &str) -> Result<(), Error> {
+        let Some(peer) = peer else {
+            error!("Client certificate required for alias {}", alias);
+            return Err(actix_web::error::ErrorUnauthorized("Client certificate required"));
+        };
+
+        if !self.allows(peer) {
+            error!("Client certificate {} is not allowed for alias {}", peer.subject, alias);
+            return Err(actix_web::error::ErrorForbidden("Client certificate not allowed"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare a SAN with an allowlist entry, where a leading `*.` stands for exactly one DNS label
+fn san_matches(allowed: &str, san: &str) -> bool {
+    match allowed.strip_prefix("*.") {
+        Some(suffix) => san
+            .split_once('.')
+            .is_some_and(|(label, rest)| is_dns_label(label) && rest.eq_ignore_ascii_case(suffix)),
+        None => allowed.eq_ignore_ascii_case(san),
+    }
+}
+
+fn is_dns_label(label: &str) -> bool {
+    !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// TLS server config with client certificate verification: none unless `SSL_CLIENT_CA_FILE`
+/// is set, and then optional unless `SSL_CLIENT_AUTH=required`
+pub fn tls_config_builder() -> Result<ConfigBuilder<ServerConfig, WantsServerCert>, String> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+
+    let Ok(ca_file) = env::var(CLIENT_CA_FILE_ENV) else {
+        return Ok(builder.with_client_cert_verifier(NoClientAuth::new()));
+    };
+
+    let reader = &mut BufReader::new(
+        fs::File::open(&ca_file).map_err(|e| format!("Failed to open {} ({}): {}", CLIENT_CA_FILE_ENV, ca_file, e))?
+    );
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(
+        &rustls_pemfile::certs(reader).map_err(|e| format!("Failed to read {}: {}", ca_file, e))?
+    );
+    if added == 0 {
+        return Err(format!("No CA certificates found in {}", ca_file));
+    }
+
+    let verifier = match env::var(CLIENT_AUTH_ENV).unwrap_or_default().to_lowercase().as_str() {
+        "required" => AllowAnyAuthenticatedClient::new(roots),
+        "" | "optional" => AllowAnyAnonymousOrAuthenticatedClient::new(roots),
+        other => return Err(format!("Invalid {}: {}. Expected 'optional' or 'required'", CLIENT_AUTH_ENV, other)),
+    };
+    Ok(builder.with_client_cert_verifier(verifier))
+}
+
+/// `HttpServer::on_connect` hook that keeps the verified client certificate with the connection
+pub fn record_peer_certificate(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+
+    let (_, session) = stream.get_ref();
+    if let Some(der) = session.peer_certificates().and_then(|certs| certs.first()) {
+        match PeerCertificate::from_der(&der.0) {
+            Ok(peer) => {
+                data.insert(peer);
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
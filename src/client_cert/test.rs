@@ -0,0 +1,72 @@
+#[allow(unused_imports)]
+use super::*;
+
+#[cfg(test)]
+mod test_client_cert {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    // Self-signed test certificate with DNS, email and IP alternative names
+    const CLIENT_CERT: &str = "-----BEGIN CERTIFICATE-----\n\
+MIICBTCCAaugAwIBAgIUWuvdWQ1Z0LgweMwL+pIoRyICULgwCgYIKoZIzj0EAwIw\n\
+NzEUMBIGA1UECgwLUGFydG5lciBJbmMxHzAdBgNVBAMMFmNsaWVudC5wYXJ0bmVy\n\
+LmV4YW1wbGUwIBcNMjYxMDE3MDQwMTQzWhgPMjEyNjA5MjMwNDAxNDNaMDcxFDAS\n\
+BgNVBAoMC1BhcnRuZXIgSW5jMR8wHQYDVQQDDBZjbGllbnQucGFydG5lci5leGFt\n\
+cGxlMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEAGxVlhXKvXeVimMhIUlyVX/k\n\
+VYnhpq6hyaexd/IZccoztzqK2Ptb5cNv2iTUMKKoHFjFPcsljrj3jI8QgZ90JKOB\n\
+kjCBjzAdBgNVHQ4EFgQUhQ2ApGw+ucL5n5RzCPIvCE6cP9IwHwYDVR0jBBgwFoAU\n\
+hQ2ApGw+ucL5n5RzCPIvCE6cP9IwDwYDVR0TAQH/BAUwAwEB/zA8BgNVHREENTAz\n\
+ghZjbGllbnQucGFydG5lci5leGFtcGxlgRNvcHNAcGFydG5lci5leGFtcGxlhwQK\n\
+AQIDMAoGCCqGSM49BAMCA0gAMEUCIQC242BMb1abzdn+BhrEJWQ5i28wMKWTWyi0\n\
+mzHmb9Yn1AIgb89ZM+Z788x5Q/J435I+oGaCRGekxVWTOmvwlEuZWZg=\n\
+-----END CERTIFICATE-----";
+
+    fn peer() -> PeerCertificate {
+        let der = rustls_pemfile::certs(&mut CLIENT_CERT.as_bytes()).unwrap().remove(0);
+        PeerCertificate::from_der(&der).unwrap()
+    }
+
+    fn config(config_json: serde_json::Value) -> ClientCertConfig {
+        serde_json::from_value(config_json).unwrap()
+    }
+
+    #[test]
+    fn test_subject_and_sans_are_read() {
+        let peer = peer();
+
+        assert_eq!(peer.get_subject(), "O=Partner Inc, CN=client.partner.example");
+        assert_eq!(peer.get_sans(), ["client.partner.example", "ops@partner.example", "10.1.2.3"]);
+        assert!(PeerCertificate::from_der(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_allowlists() {
+        let peer = peer();
+
+        assert!(config(json!({})).allows(&peer));
+        assert!(config(json!({"subjects": ["O=Partner Inc, CN=client.partner.example"]})).allows(&peer));
+        assert!(config(json!({"sans": ["CLIENT.partner.example"]})).allows(&peer));
+        assert!(config(json!({"sans": ["*.partner.example"]})).allows(&peer));
+        assert!(config(json!({"sans": ["10.1.2.3"]})).allows(&peer));
+        assert!(config(json!({"subjects": ["CN=other"], "sans": ["ops@partner.example"]})).allows(&peer));
+
+        assert!(!config(json!({"subjects": ["CN=client.partner.example"]})).allows(&peer));
+        assert!(!config(json!({"sans": ["*.example"]})).allows(&peer));
+        assert!(!config(json!({"sans": ["partner.example"]})).allows(&peer));
+    }
+
+    #[test]
+    fn test_check_status() {
+        let config = config(json!({"sans": ["client.partner.example"]}));
+
+        assert!(config.check(Some(&peer()), "partner").is_ok());
+
+        let missing = config.check(None, "partner").unwrap_err();
+        assert_eq!(missing.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+        let other = ClientCertConfig { subjects: Vec::new(), sans: vec!["other.example".to_string()] };
+        let denied = other.check(Some(&peer()), "partner").unwrap_err();
+        assert_eq!(denied.as_response_error().status_code(), StatusCode::FORBIDDEN);
+    }
+}
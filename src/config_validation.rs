@@ -0,0 +1,473 @@
+// config_validation.rs
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use actix_web::http::Method;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use crate::data_handler::data_config::{ChallengeCondition, EndpointDataMap, EnvelopeConfig};
+use crate::data_handler::signature_config::{SignatureConfig, SignatureScheme, VerifyingKey};
+use crate::verification_handler::extractors::LOCATIONS;
+use crate::verification_handler::template::{render_structured, render_template, Escape, TemplateContext};
+use crate::verification_handler::verification_config::{ContentType, ResponseConfig, ResponseData, VerificationConfig};
+use crate::client_cert::{ClientCertConfig, CLIENT_CA_FILE_ENV};
+use crate::jwt_auth::JwtConfig;
+use crate::path_pattern::PathPattern;
+use crate::source_ip::{IpAllowlist, TrustedProxies};
+use crate::token_set::TokenSet;
+use crate::webhook_config::WebhookConfig;
+use crate::{ADMIN_BANS_PATH, CALLBACK_PATH, POLLING_PATH};
+
+pub mod test;
+
+const STANDARD_METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+
+// Define an enum for how serious a validation issue is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,   // The server refuses to start
+    Warning, // Likely a mistake, but requests can still be served
+}
+
+/// A problem found in the config, with the dotted YAML location it was found at
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    severity: Severity,
+    location: String,
+    message: String,
+}
+
+impl ValidationIssue {
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn get_location(&self) -> String {
+        self.location.clone()
+    }
+
+    pub fn get_message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+/// Collects issues while the config is walked
+#[derive(Default)]
+struct Validator {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator {
+    fn error(&mut self, location: &str, message: String) {
+        self.issues.push(ValidationIssue { severity: Severity::Error, location: location.to_string(), message });
+    }
+
+    fn warning(&mut self, location: &str, message: String) {
+        self.issues.push(ValidationIssue { severity: Severity::Warning, location: location.to_string(), message });
+    }
+
+    /// Methods are matched case-sensitively, so `post` would never match a POST request
+    fn check_method(&mut self, location: &str, method: &str) {
+        if Method::from_bytes(method.as_bytes()).is_err() {
+            self.error(location, format!("'{}' is not a valid HTTP method", method));
+            return;
+        }
+
+        let upper = method.to_uppercase();
+        if upper != method && STANDARD_METHODS.contains(&upper.as_str()) {
+            self.error(location, format!("methods are case-sensitive, use '{}' instead of '{}'", upper, method));
+        } else if !STANDARD_METHODS.contains(&method) {
+            self.warning(location, format!("'{}' is not a standard HTTP method", method));
+        }
+    }
+
+    /// Patterns such as `/a/**/**/b` match the same paths in several ways, so captures are unpredictable
+    fn check_path_pattern(&mut self, location: &str, pattern: &PathPattern) {
+        if pattern.is_ambiguous() {
+            self.warning(
+                location,
+                format!("'{}' has two multi-segment wildcards with no literal segment between them", pattern.as_str()),
+            );
+        }
+    }
+
+    /// `in: param` must name a `{param}` segment of the block's own path
+    fn check_param(&mut self, location: &str, source: &str, locate: &str, path: &PathPattern) {
+        if source == "param" && !path.param_names().iter().any(|name| name == locate) {
+            self.error(
+                &format!("{}.locate", location),
+                format!("'{}' is not a {{param}} segment of path '{}'", locate, path.as_str()),
+            );
+        }
+    }
+
+    fn check_extractor(&mut self, location: &str, source: &str, locate: &str) {
+        if !LOCATIONS.contains(&source) {
+            self.error(
+                &format!("{}.in", location),
+                format!("unknown location '{}'. Expected one of: {}", source, LOCATIONS.join(", ")),
+            );
+        }
+
+        if locate.trim().is_empty() {
+            self.error(&format!("{}.locate", location), "must not be empty".to_string());
+        } else if source == "path" && locate.parse::<usize>().is_err() {
+            self.error(
+                &format!("{}.locate", location),
+                format!("path locations take a segment index, got '{}'", locate),
+            );
+        }
+    }
+
+    fn check_response(&mut self, location: &str, response: &ResponseConfig) {
+        if response.get_in_path().is_some() && response.get_content_type() != ContentType::ApplicationJson {
+            self.warning(
+                &format!("{}.in_path", location),
+                format!("only used by application/json responses, ignored for {}", response.get_content_type().as_str()),
+            );
+        }
+
+        // `@hmac_*` secrets are only read at startup, so an unset one is a warning and templates render with placeholders
+        for secret_env in response.secret_envs() {
+            self.check_env(location, &secret_env);
+        }
+        let secrets: HashMap<String, String> = response.secret_envs().into_iter().map(|env| (env, String::new())).collect();
+        let context = || TemplateContext::new("challenge").with_secrets(&secrets);
+
+        let rendered = match response.get_data() {
+            ResponseData::Text(template) => render_template(template, &context()).map(|_| ()),
+            ResponseData::Structured(data) => {
+                if Escape::for_content_type(response.get_content_type().as_str()) != Escape::Json {
+                    self.warning(
+                        &format!("{}.data", location),
+                        format!("structured data is rendered as JSON, but the type is {}", response.get_content_type().as_str()),
+                    );
+                }
+                render_structured(data, &context()).map(|_| ())
+            }
+        };
+        if let Err(e) = rendered {
+            self.error(&format!("{}.data", location), e);
+        }
+
+        let mut headers: Vec<_> = response.get_headers().iter().collect();
+        headers.sort();
+        for (name, value) in headers {
+            let header_location = format!("{}.headers.{}", location, name);
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                self.error(&header_location, format!("'{}' is not a valid header name", name));
+            }
+            match render_template(value, &context()) {
+                Ok(rendered) if HeaderValue::from_str(&rendered).is_err() => {
+                    self.error(&header_location, "value is not a valid header value".to_string());
+                }
+                Ok(_) => {}
+                Err(e) => self.error(&header_location, e),
+            }
+        }
+    }
+
+    fn check_env(&mut self, location: &str, var: &str) {
+        if env::var(var).is_err() {
+            self.warning(location, format!("{} is not set in this environment", var));
+        }
+    }
+
+    /// Tokens may come from the secrets file named by `VAR_FILE`, the list in `VAR_LIST`, or the single token in `VAR`
+    fn check_token_env(&mut self, location: &str, var: &str) {
+        if let Err(e) = TokenSet::from_env(var) {
+            self.warning(location, format!("{} in this environment", e));
+            return;
+        }
+
+        // `VAR` is one literal token, which is rarely what a comma was meant for
+        let list_set = env::var(format!("{}_FILE", var)).is_ok() || env::var(format!("{}_LIST", var)).is_ok();
+        if !list_set && let Ok(token) = env::var(var) && token.contains(',') {
+            self.warning(
+                location,
+                format!("{} is used as one token, commas included; set {}_LIST for several tokens", var, var),
+            );
+        }
+    }
+
+    fn check_verification(&mut self, location: &str, config: &VerificationConfig) {
+        if !config.is_verification_path_valid() {
+            self.error(
+                &format!("{}.path", location),
+                format!("'{}' must start with /{}/, requests to it are never matched", config.get_verification_path(), CALLBACK_PATH),
+            );
+        }
+
+        self.check_path_pattern(&format!("{}.path", location), config.get_path_pattern());
+        self.check_method(&format!("{}.method", location), &config.get_verification_method());
+
+        let path = config.get_path_pattern();
+        match (config.get_token_config(), config.get_jwt_config()) {
+            (None, None) => self.error(location, "needs a token or jwt check, or every request is accepted".to_string()),
+            (Some(token), _) => {
+                self.check_token_env(&format!("{}.token_env", location), &config.get_token_env());
+                self.check_extractor(&format!("{}.token", location), &token.get_in(), &token.get_locate());
+                self.check_param(&format!("{}.token", location), &token.get_in(), &token.get_locate(), path);
+            }
+            (None, Some(_)) => {}
+        }
+        if let Some(jwt) = config.get_jwt_config() {
+            self.check_jwt(&format!("{}.jwt", location), jwt);
+        }
+
+        if let Some(allowed_ips) = config.get_allowed_ips() {
+            self.check_allowed_ips(&format!("{}.allowed_ips", location), allowed_ips);
+        }
+
+        let challenge = config.get_challenge_config();
+        self.check_extractor(&format!("{}.challenge", location), &challenge.get_in(), &challenge.get_locate());
+        self.check_param(&format!("{}.challenge", location), &challenge.get_in(), &challenge.get_locate(), path);
+
+        self.check_response(&format!("{}.response", location), config.get_response_config());
+        if let Some(failure_response) = config.get_failure_response_config() {
+            self.check_response(&format!("{}.failure_response", location), failure_response);
+        }
+    }
+
+    fn check_jwt(&mut self, location: &str, jwt: &JwtConfig) {
+        match (jwt.get_jwks_file(), jwt.get_jwks_url()) {
+            (None, None) => self.error(location, "needs either jwks_file or jwks_url".to_string()),
+            (Some(_), Some(_)) => self.error(location, "set only one of jwks_file and jwks_url".to_string()),
+            (Some(_), None) => {
+                if let Err(e) = jwt.clone().load_keys() {
+                    self.warning(&format!("{}.jwks_file", location), format!("{} in this environment", e));
+                }
+            }
+            (None, Some(url)) if !url.starts_with("https://") => {
+                self.warning(&format!("{}.jwks_url", location), format!("'{}' is not fetched over HTTPS", url));
+            }
+            (None, Some(_)) => {}
+        }
+
+        for (field, values) in [("audience", jwt.get_audience()), ("issuer", jwt.get_issuer())] {
+            if values.is_empty() || values.iter().any(|value| value.trim().is_empty()) {
+                self.error(&format!("{}.{}", location, field), "must not be empty".to_string());
+            }
+        }
+
+        if HeaderName::from_bytes(jwt.get_header().as_bytes()).is_err() {
+            self.error(&format!("{}.header", location), format!("'{}' is not a valid header name", jwt.get_header()));
+        }
+    }
+
+    /// Client certificates are only requested when the TLS listener has a CA bundle
+    fn check_client_cert(&mut self, location: &str, client_cert: &ClientCertConfig) {
+        if env::var(CLIENT_CA_FILE_ENV).is_err() {
+            self.warning(
+                location,
+                format!("{} is not set in this environment, so every request to this alias is rejected", CLIENT_CA_FILE_ENV),
+            );
+        }
+
+        for (field, values) in [("subjects", client_cert.get_subjects()), ("sans", client_cert.get_sans())] {
+            if values.iter().any(|value| value.trim().is_empty()) {
+                self.error(&format!("{}.{}", location, field), "entries must not be empty".to_string());
+            }
+        }
+    }
+
+    fn check_allowed_ips(&mut self, location: &str, allowed_ips: &IpAllowlist) {
+        match allowed_ips.get_file() {
+            None if allowed_ips.get_ranges().is_empty() => {
+                self.error(location, "needs ranges or a file, or every request is rejected".to_string());
+            }
+            Some(_) => {
+                if let Err(e) = allowed_ips.clone().load_ranges() {
+                    self.warning(&format!("{}.file", location), format!("{} in this environment", e));
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn check_envelope(&mut self, location: &str, envelope: &EnvelopeConfig) {
+        let lists = [("include_headers", envelope.get_include_headers()), ("exclude_headers", envelope.get_exclude_headers())];
+        if lists.iter().all(|(_, headers)| headers.is_some()) {
+            self.error(location, "set only one of include_headers and exclude_headers".to_string());
+        }
+
+        for (field, headers) in lists {
+            for name in headers.unwrap_or_default() {
+                if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    self.error(&format!("{}.{}", location, field), format!("'{}' is not a valid header name", name));
+                }
+            }
+        }
+    }
+
+    /// A proxy range covering every address lets any client choose its own source IP
+    fn check_trusted_proxies(&mut self, location: &str, proxies: &TrustedProxies) {
+        if let Some(range) = proxies.get_ranges().iter().find(|range| range.is_everything()) {
+            self.warning(
+                &format!("{}.ranges", location),
+                format!("{:?} trusts every address, so any client can set its own source IP", range),
+            );
+        }
+    }
+
+    fn check_signature(&mut self, location: &str, signature: &SignatureConfig) {
+        let scheme = signature.get_scheme();
+        if scheme.uses_public_key() {
+            match signature.get_public_key() {
+                Some(public_key) => {
+                    if let Err(e) = VerifyingKey::parse(scheme, &public_key) {
+                        self.error(&format!("{}.public_key", location), e);
+                    }
+                }
+                None => self.error(&format!("{}.public_key", location), format!("required by the {} scheme", scheme.as_str())),
+            }
+        } else if signature.get_secret_env().is_empty() {
+            self.error(&format!("{}.secret_env", location), format!("required by the {} scheme", scheme.as_str()));
+        } else {
+            self.check_env(&format!("{}.secret_env", location), &signature.get_secret_env());
+        }
+
+        let uses_header = matches!(scheme, SignatureScheme::Hmac) || scheme.uses_public_key();
+        if uses_header && signature.get_header().trim().is_empty() {
+            self.error(&format!("{}.header", location), format!("required by the {} scheme", scheme.as_str()));
+        }
+
+        match signature.get_timestamp_config() {
+            Some(timestamp) => {
+                if timestamp.get_key().is_some() && signature.get_signature_key().is_none() {
+                    self.error(
+                        &format!("{}.timestamp.key", location),
+                        "only read from structured headers, set signature_key as well".to_string(),
+                    );
+                }
+                if timestamp.get_key().is_none() && timestamp.get_header().is_none() {
+                    self.error(&format!("{}.timestamp", location), "needs either header or key".to_string());
+                }
+            }
+            None if signature.get_template().contains("{timestamp}") => {
+                self.warning(
+                    &format!("{}.template", location),
+                    "uses {timestamp} but no timestamp is configured, it is signed as empty".to_string(),
+                );
+            }
+            None => {}
+        }
+    }
+
+    fn check_condition(&mut self, location: &str, condition: &ChallengeCondition) {
+        self.check_extractor(location, &condition.get_in(), &condition.get_locate());
+    }
+
+    fn check_data(&mut self, location: &str, endpoint: &EndpointDataMap) {
+        let path = endpoint.get_path_pattern();
+        let reserved = [
+            format!("/{}/*", POLLING_PATH),
+            format!("/{}/{}", POLLING_PATH, ADMIN_BANS_PATH),
+            "/health".to_string(),
+        ];
+
+        // Data routes are registered as catch-alls, so a pattern that reaches a server route would take it over
+        if let Some(route) = reserved.iter().find(|route| PathPattern::parse(route).is_ok_and(|route| route.overlaps(path))) {
+            self.error(
+                &format!("{}.path", location),
+                format!("'{}' matches {}, which is reserved by the server", path.as_str(), route),
+            );
+        }
+
+        self.check_path_pattern(&format!("{}.path", location), path);
+        self.check_method(&format!("{}.method", location), &endpoint.get_method());
+
+        if let Some(signature) = endpoint.get_signature_config() {
+            self.check_signature(&format!("{}.signature", location), signature);
+        }
+
+        if let Some(jwt) = endpoint.get_jwt_config() {
+            self.check_jwt(&format!("{}.jwt", location), jwt);
+        }
+
+        if let Some(client_cert) = endpoint.get_client_cert_config() {
+            self.check_client_cert(&format!("{}.client_cert", location), client_cert);
+        }
+
+        if let Some(allowed_ips) = endpoint.get_allowed_ips() {
+            self.check_allowed_ips(&format!("{}.allowed_ips", location), allowed_ips);
+        }
+
+        if let Some(envelope) = endpoint.get_envelope_config() {
+            self.check_envelope(&format!("{}.envelope", location), envelope);
+        }
+
+        for (index, source) in endpoint.get_dedup_key().iter().enumerate() {
+            let source_location = format!("{}.dedup_key.{}", location, index);
+            self.check_extractor(&source_location, &source.get_in(), &source.get_locate());
+            self.check_param(&source_location, &source.get_in(), &source.get_locate(), path);
+        }
+
+        if let Some(challenge) = endpoint.get_challenge_config() {
+            let condition = challenge.get_condition();
+            self.check_condition(&format!("{}.challenge.when", location), condition);
+            self.check_param(&format!("{}.challenge.when", location), &condition.get_in(), &condition.get_locate(), path);
+            if let Some(value) = challenge.get_challenge_config() {
+                self.check_extractor(&format!("{}.challenge.value", location), &value.get_in(), &value.get_locate());
+                self.check_param(&format!("{}.challenge.value", location), &value.get_in(), &value.get_locate(), path);
+            }
+            self.check_response(&format!("{}.challenge.response", location), challenge.get_response_config());
+        }
+    }
+}
+
+/// Check the whole config and return every issue found, ordered by block name and alias
+pub fn validate_config(config: &WebhookConfig) -> Vec<ValidationIssue> {
+    let mut validator = Validator::default();
+    validator.check_trusted_proxies("trusted_proxies", config.get_trusted_proxies());
+    let verification_map = config.get_verification_map();
+
+    // Every route registered for a method, in registration order, so conflicts are reported on the later one
+    let mut routes: Vec<(String, String, PathPattern)> = Vec::new();
+    let mut check_conflicts = |validator: &mut Validator, location: String, method: String, pattern: &PathPattern| {
+        for (other, other_method, other_pattern) in &routes {
+            if *other_method == method && other_pattern.overlaps(pattern) {
+                validator.error(
+                    &format!("{}.path", location),
+                    format!("overlaps {} for the same method, requests matching both only reach {}", other, other),
+                );
+            }
+        }
+        routes.push((location, method, pattern.clone()));
+    };
+
+    for (name, method, verification) in verification_map.get_name_method_config_vec() {
+        let location = verification_map.get_yaml_location(&name);
+        validator.check_verification(&location, &verification);
+
+        // A verification path outside the callback prefix is never matched, and already reported
+        if verification.is_verification_path_valid() {
+            check_conflicts(&mut validator, location, method, verification.get_path_pattern());
+        }
+    }
+
+    let mut aliases: Vec<_> = config.get_data_config().0.iter().collect();
+    aliases.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (alias, endpoint) in aliases {
+        let location = format!("data.{}", alias);
+        validator.check_data(&location, endpoint);
+        check_conflicts(&mut validator, location, endpoint.get_method(), endpoint.get_path_pattern());
+    }
+
+    validator.issues
+}
+
+/// Number of issues that stop the server from starting
+pub fn count_errors(issues: &[ValidationIssue]) -> usize {
+    issues.iter().filter(|issue| issue.get_severity() == Severity::Error).count()
+}
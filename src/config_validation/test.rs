@@ -0,0 +1,284 @@
+#[allow(unused_imports)]
+use super::*;
+
+#[cfg(test)]
+mod test_config_validation {
+    use super::*;
+
+    fn issues_for(yaml: &str) -> Vec<ValidationIssue> {
+        let config: WebhookConfig = serde_yaml::from_str(yaml).unwrap();
+        validate_config(&config)
+    }
+
+    fn has_issue(issues: &[ValidationIssue], severity: Severity, location: &str) -> bool {
+        issues.iter().any(|issue| issue.get_severity() == severity && issue.get_location() == location)
+    }
+
+    const VALID: &str = r#"
+verification:
+  path: /callhook/meta
+  token_env: PATH
+  token:
+    in: query
+    locate: hub.verify_token
+  challenge:
+    in: query
+    locate: hub.challenge
+  response:
+    type: text/plain
+    data: "@challenge"
+data:
+  meta_event:
+    path: /callhook/meta
+    method: POST
+"#;
+
+    #[test]
+    fn test_valid_config_has_no_issues() {
+        let issues = issues_for(VALID);
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn test_every_problem_is_reported_with_its_location() {
+        let issues = issues_for(r#"
+verification:
+  meta:
+    path: /pollhook/meta
+    method: "GE T"
+    token_env: PATH
+    token:
+      in: cookie
+      locate: token
+    challenge:
+      in: path
+      locate: challenge
+    response:
+      type: text/plain
+      in_path: challenge
+      data: "@nope(@challenge)"
+data:
+  events:
+    path: /callhook/events
+    method: post
+    signature:
+      header: ""
+      secret_env: PATH
+      timestamp:
+        key: t
+  reserved:
+    path: /pollhook/events
+    method: POST
+"#);
+
+        assert!(has_issue(&issues, Severity::Error, "verification.meta.path"));
+        assert!(has_issue(&issues, Severity::Error, "verification.meta.method"));
+        assert!(has_issue(&issues, Severity::Error, "verification.meta.token.in"));
+        assert!(has_issue(&issues, Severity::Error, "verification.meta.challenge.locate"));
+        assert!(has_issue(&issues, Severity::Warning, "verification.meta.response.in_path"));
+        assert!(has_issue(&issues, Severity::Error, "verification.meta.response.data"));
+        assert!(has_issue(&issues, Severity::Error, "data.events.method"));
+        assert!(has_issue(&issues, Severity::Error, "data.events.signature.header"));
+        assert!(has_issue(&issues, Severity::Error, "data.events.signature.timestamp.key"));
+        assert!(has_issue(&issues, Severity::Error, "data.reserved.path"));
+        assert_eq!(count_errors(&issues), 9);
+    }
+
+    #[test]
+    fn test_route_conflicts() {
+        let issues = issues_for(r#"
+verification:
+  path: /callhook/.../events
+  method: POST
+  token_env: PATH
+  token:
+    in: header
+    locate: X-Token
+  challenge:
+    in: body
+    locate: challenge
+  response:
+    type: text/plain
+    data: "@challenge"
+data:
+  first:
+    path: /callhook/a/events
+    method: POST
+  second:
+    path: callhook/b
+    method: POST
+  third:
+    path: /callhook/b/
+    method: POST
+  fourth:
+    path: /callhook/*/orders
+    method: POST
+  fifth:
+    path: /callhook/**/**/orders
+    method: GET
+"#);
+
+        // The legacy single block is reported at `verification`
+        assert!(issues.iter().any(|issue| issue.get_message().contains("overlaps verification for the same method")));
+        assert!(has_issue(&issues, Severity::Error, "data.first.path"));
+        assert!(has_issue(&issues, Severity::Error, "data.third.path"));
+        assert!(has_issue(&issues, Severity::Warning, "data.fifth.path"));
+        assert_eq!(count_errors(&issues), 2);
+    }
+
+    #[test]
+    fn test_jwt_checks() {
+        let issues = issues_for(r#"
+verification:
+  pubsub:
+    path: /callhook/pubsub
+    method: POST
+    jwt:
+      jwks_url: http://www.googleapis.com/oauth2/v3/certs
+      audience: https://hooks.example.com
+      issuer: https://accounts.google.com
+    challenge:
+      in: query
+      locate: challenge
+    response:
+      type: text/plain
+      data: "@challenge"
+  open:
+    path: /callhook/open
+    challenge:
+      in: query
+      locate: challenge
+    response:
+      type: text/plain
+      data: "@challenge"
+data:
+  events:
+    path: /callhook/events
+    method: POST
+    jwt:
+      audience: []
+      issuer: https://accounts.google.com
+"#);
+
+        assert!(has_issue(&issues, Severity::Warning, "verification.pubsub.jwt.jwks_url"));
+        assert!(has_issue(&issues, Severity::Error, "verification.open"));
+        assert!(has_issue(&issues, Severity::Error, "data.events.jwt"));
+        assert!(has_issue(&issues, Severity::Error, "data.events.jwt.audience"));
+        assert_eq!(count_errors(&issues), 3);
+    }
+
+    #[test]
+    fn test_allowed_ips_checks() {
+        let issues = issues_for(r#"
+trusted_proxies:
+  ranges: [0.0.0.0/0]
+verification:
+  path: /callhook/meta
+  token_env: PATH
+  token:
+    in: query
+    locate: hub.verify_token
+  allowed_ips:
+    file: /nonexistent/pollhook/meta-ranges.txt
+  challenge:
+    in: query
+    locate: hub.challenge
+  response:
+    type: text/plain
+    data: "@challenge"
+data:
+  github:
+    path: /callhook/github
+    method: POST
+    allowed_ips: {}
+  stripe:
+    path: /callhook/stripe
+    method: POST
+    allowed_ips:
+      ranges: [3.18.12.63, 2a0a:a440::/29]
+"#);
+
+        assert!(has_issue(&issues, Severity::Warning, "trusted_proxies.ranges"));
+        assert!(has_issue(&issues, Severity::Warning, "verification.allowed_ips.file"));
+        assert!(has_issue(&issues, Severity::Error, "data.github.allowed_ips"));
+        assert_eq!(count_errors(&issues), 1);
+    }
+
+    #[test]
+    fn test_envelope_checks() {
+        let issues = issues_for(r#"
+verification: {}
+data:
+  github:
+    path: /callhook/github
+    method: POST
+    envelope:
+      include_headers: [X-GitHub-Event, "Bad Header"]
+      exclude_headers: [Authorization]
+"#);
+
+        assert!(has_issue(&issues, Severity::Error, "data.github.envelope"));
+        assert!(has_issue(&issues, Severity::Error, "data.github.envelope.include_headers"));
+        assert_eq!(count_errors(&issues), 2);
+    }
+
+    #[test]
+    fn test_dedup_key_checks() {
+        let issues = issues_for(r#"
+verification: {}
+data:
+  acme:
+    path: /callhook/acme
+    method: POST
+    dedup_key:
+      - in: header
+        locate: X-Tenant
+      - in: cookie
+        locate: id
+"#);
+
+        assert!(has_issue(&issues, Severity::Error, "data.acme.dedup_key.1.in"));
+        assert_eq!(count_errors(&issues), 1);
+    }
+
+    #[test]
+    fn test_unset_env_is_a_warning() {
+        let issues = issues_for(&VALID.replace("token_env: PATH", "token_env: POLLHOOK_TEST_UNSET_TOKEN"));
+
+        assert!(has_issue(&issues, Severity::Warning, "verification.token_env"));
+        assert_eq!(count_errors(&issues), 0);
+        assert_eq!(
+            issues[0].to_string(),
+            "warning: verification.token_env: POLLHOOK_TEST_UNSET_TOKEN is not set in this environment"
+        );
+    }
+
+    #[test]
+    fn test_comma_in_single_token_is_a_warning() {
+        unsafe {
+            std::env::set_var("POLLHOOK_TEST_COMMA_TOKEN", "new-token,old-token");
+        }
+        let issues = issues_for(&VALID.replace("token_env: PATH", "token_env: POLLHOOK_TEST_COMMA_TOKEN"));
+
+        assert!(has_issue(&issues, Severity::Warning, "verification.token_env"));
+        assert!(issues[0].get_message().contains("POLLHOOK_TEST_COMMA_TOKEN_LIST"));
+        assert_eq!(count_errors(&issues), 0);
+    }
+
+    #[test]
+    fn test_unset_hmac_secret_is_a_warning() {
+        let issues = issues_for(&VALID.replace(
+            "data: \"@challenge\"",
+            "data: \"@hmac_sha256_hex(POLLHOOK_TEST_UNSET_SECRET, @challenge)\"",
+        ));
+        assert!(has_issue(&issues, Severity::Warning, "verification.response"));
+        assert_eq!(count_errors(&issues), 0);
+
+        // Template syntax problems are still errors
+        let issues = issues_for(&VALID.replace(
+            "data: \"@challenge\"",
+            "data: \"@hmac_sha256_hex(POLLHOOK_TEST_UNSET_SECRET, @challenge\"",
+        ));
+        assert!(has_issue(&issues, Severity::Error, "verification.response.data"));
+    }
+}
@@ -1,2 +1,8 @@
 pub mod data_config;
-pub mod data_receiver;
\ No newline at end of file
+pub mod data_receiver;
+pub mod envelope;
+pub mod in_band_challenge;
+pub mod replay_guard;
+pub mod signature;
+pub mod signature_config;
+pub mod test;
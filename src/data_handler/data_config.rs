@@ -1,31 +1,264 @@
 use std::collections::HashMap;
+use std::time::Duration;
 use serde::{Deserialize};
+use crate::data_handler::signature_config::SignatureConfig;
+use crate::client_cert::ClientCertConfig;
+use crate::jwt_auth::JwtConfig;
+use crate::path_pattern::PathPattern;
+use crate::source_ip::IpAllowlist;
+use crate::verification_handler::verification_config::{CapturePattern, ChallengeConfig, ResponseConfig};
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct EndpointDataMap {
-    path: String,
+    path: PathPattern,
     #[serde(default = "default_method")]
     method: String,
+    #[serde(default)]
+    signature: Option<SignatureConfig>,
+    #[serde(default)]
+    jwt: Option<JwtConfig>,
+    #[serde(default)]
+    client_cert: Option<ClientCertConfig>,
+    #[serde(default)]
+    allowed_ips: Option<IpAllowlist>,
+    #[serde(default)]
+    envelope: Option<EnvelopeConfig>, // Record request details next to the body
+    #[serde(default)]
+    dedup_key: Vec<DedupKeySource>, // Parts of the event ID, the body hash when empty
+    #[serde(default)]
+    challenge: Option<InBandChallengeConfig>,
+}
+
+/// One part of an alias's dedup key, e.g. the `X-GitHub-Delivery` header
+#[derive(Clone, Debug, Deserialize)]
+pub struct DedupKeySource {
+    #[serde(rename = "in")]
+    location: String,
+    locate: String,
+    #[serde(default)]
+    pattern: Option<CapturePattern>,
+}
+
+impl DedupKeySource {
+    pub fn get_in(&self) -> String {
+        self.location.clone()
+    }
+
+    pub fn get_locate(&self) -> String {
+        self.locate.clone()
+    }
+
+    pub fn get_pattern(&self) -> Option<&CapturePattern> {
+        self.pattern.as_ref()
+    }
+}
+
+/// Request details cached with each event of an alias. Headers are only recorded when
+/// `include_headers` lists them, or, with `exclude_headers`, unless it lists them.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EnvelopeConfig {
+    #[serde(default)]
+    include_headers: Option<Vec<String>>,
+    #[serde(default)]
+    exclude_headers: Option<Vec<String>>,
+    #[serde(default)]
+    raw_body: bool, // Keep the exact body bytes, e.g. to check a signature again
+}
+
+impl EnvelopeConfig {
+    pub fn get_include_headers(&self) -> Option<&[String]> {
+        self.include_headers.as_deref()
+    }
+
+    pub fn get_exclude_headers(&self) -> Option<&[String]> {
+        self.exclude_headers.as_deref()
+    }
+
+    pub fn keeps_raw_body(&self) -> bool {
+        self.raw_body
+    }
+
+    pub fn records_header(&self, name: &str) -> bool {
+        match (&self.include_headers, &self.exclude_headers) {
+            (Some(include), _) => include.iter().any(|header| header.eq_ignore_ascii_case(name)),
+            (None, Some(exclude)) => !exclude.iter().any(|header| header.eq_ignore_ascii_case(name)),
+            (None, None) => false,
+        }
+    }
+}
+
+/// Predicate on a request field, e.g. body `type` equal to `url_verification`
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChallengeCondition {
+    #[serde(rename = "in")]
+    location: String,
+    locate: String,
+    #[serde(default)]
+    pattern: Option<CapturePattern>,
+    #[serde(default)]
+    equals: Option<String>, // When absent, the field only has to be present
+}
+
+impl ChallengeCondition {
+    pub fn get_in(&self) -> String {
+        self.location.clone()
+    }
+
+    pub fn get_locate(&self) -> String {
+        self.locate.clone()
+    }
+
+    pub fn get_pattern(&self) -> Option<&CapturePattern> {
+        self.pattern.as_ref()
+    }
+
+    pub fn get_equals(&self) -> Option<String> {
+        self.equals.clone()
+    }
+}
+
+/// Verification challenge sent in-band to a data path (Slack `url_verification` style).
+/// Matching requests are answered from `response` and never cached. Without `value` the
+/// request is a ping that only needs a fixed reply (Discord `{"type":1}` style).
+#[derive(Clone, Debug, Deserialize)]
+pub struct InBandChallengeConfig {
+    when: ChallengeCondition,
+    #[serde(default)]
+    value: Option<ChallengeConfig>,
+    response: ResponseConfig,
+}
+
+impl InBandChallengeConfig {
+    pub fn get_condition(&self) -> &ChallengeCondition {
+        &self.when
+    }
+
+    pub fn get_challenge_config(&self) -> Option<&ChallengeConfig> {
+        self.value.as_ref()
+    }
+
+    pub fn get_response_config(&self) -> &ResponseConfig {
+        &self.response
+    }
 }
 
 fn default_method() -> String {
     actix_web::http::Method::GET.to_string()
 }
 
+impl EndpointDataMap {
+    pub fn get_path_pattern(&self) -> &PathPattern {
+        &self.path
+    }
+
+    pub fn get_method(&self) -> String {
+        self.method.clone()
+    }
+
+    pub fn get_signature_config(&self) -> Option<&SignatureConfig> {
+        self.signature.as_ref()
+    }
+
+    pub fn get_jwt_config(&self) -> Option<&JwtConfig> {
+        self.jwt.as_ref()
+    }
+
+    pub fn get_client_cert_config(&self) -> Option<&ClientCertConfig> {
+        self.client_cert.as_ref()
+    }
+
+    pub fn get_allowed_ips(&self) -> Option<&IpAllowlist> {
+        self.allowed_ips.as_ref()
+    }
+
+    pub fn get_dedup_key(&self) -> &[DedupKeySource] {
+        &self.dedup_key
+    }
+
+    pub fn get_envelope_config(&self) -> Option<&EnvelopeConfig> {
+        self.envelope.as_ref()
+    }
+
+    pub fn get_challenge_config(&self) -> Option<&InBandChallengeConfig> {
+        self.challenge.as_ref()
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct DataMap(pub HashMap<String, EndpointDataMap>);
 
 impl DataMap{
-    pub fn get_alias_path_method_vec(&self) -> Vec<(String, String, String)> {
-        self.0
+    pub fn get(&self, alias: &str) -> Option<&EndpointDataMap> {
+        self.0.get(alias)
+    }
+
+    /// Alias, method and path pattern of every endpoint, sorted by alias so routes register in a stable order
+    pub fn get_alias_method_pattern_vec(&self) -> Vec<(String, String, PathPattern)> {
+        let mut endpoints: Vec<_> = self.0
             .iter()
             .map(|(alias, endpoint)| {
                 (
                     alias.clone(),
-                    endpoint.path.clone(),
                     endpoint.method.clone(),
+                    endpoint.path.clone(),
                 )
             })
+            .collect();
+        endpoints.sort_by(|a, b| a.0.cmp(&b.0));
+        endpoints
+    }
+
+    /// Replay window of every alias whose signatures carry a timestamp
+    pub fn get_replay_windows(&self) -> Vec<(String, Duration)> {
+        self.0
+            .iter()
+            .filter_map(|(alias, endpoint)| {
+                let seconds = endpoint.signature.as_ref()?.get_replay_window_seconds()?;
+                Some((alias.clone(), Duration::from_secs(seconds)))
+            })
             .collect()
     }
+
+    /// Read the signing secret of every alias that verifies payload signatures
+    pub fn load_signature_secrets(&mut self) -> Result<(), String> {
+        for (alias, endpoint) in self.0.iter_mut() {
+            if let Some(signature) = endpoint.signature.as_mut() {
+                signature.load_secret()
+                    .map_err(|e| format!("{} (signature secret of data alias '{}')", e, alias))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the `@hmac_*` secrets of every alias's in-band challenge response
+    pub fn load_template_secrets(&mut self) -> Result<(), String> {
+        for (alias, endpoint) in self.0.iter_mut() {
+            if let Some(challenge) = endpoint.challenge.as_mut() {
+                challenge.response.load_secrets()
+                    .map_err(|e| format!("{} (challenge response of data alias '{}')", e, alias))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the JWKS file of every alias that verifies bearer tokens
+    pub fn load_jwks(&mut self) -> Result<(), String> {
+        for (alias, endpoint) in self.0.iter_mut() {
+            if let Some(jwt) = endpoint.jwt.as_mut() {
+                jwt.load_keys().map_err(|e| format!("{} (jwt of data alias '{}')", e, alias))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the allowlist file of every alias that restricts source addresses
+    pub fn load_ip_allowlists(&mut self) -> Result<(), String> {
+        for (alias, endpoint) in self.0.iter_mut() {
+            if let Some(allowed_ips) = endpoint.allowed_ips.as_mut() {
+                allowed_ips.load_ranges()
+                    .map_err(|e| format!("{} (allowed_ips of data alias '{}')", e, alias))?;
+            }
+        }
+        Ok(())
+    }
 }
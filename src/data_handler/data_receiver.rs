@@ -1,34 +1,98 @@
+use crate::body_format;
 use crate::cache::OrderedCache;
-use actix_web::{web};
+use crate::data_handler::data_config::DedupKeySource;
+use crate::data_handler::envelope::EventMetadata;
+use crate::verification_handler::extractors;
+use actix_web::{web, Error, HttpRequest};
+use actix_web::http::header;
+use bytes::Bytes;
 use futures::StreamExt;
+use log::debug;
 use sha2::{Sha256, Digest};
 
 
-
-pub async fn data_receiver(
-    mut payload: web::Payload,
-    alias: String,
-    cache: web::Data<OrderedCache>,
-) -> Result<(String, String), Box<dyn std::error::Error>> {
-    // Collect the payload bytes
+/// Collect the raw request body so it can be verified before it is parsed
+pub async fn collect_payload(mut payload: web::Payload) -> Result<Bytes, actix_web::Error> {
     let mut body = web::BytesMut::new();
     while let Some(chunk) = payload.next().await {
         let chunk = chunk?;
         body.extend_from_slice(&chunk);
     }
+    Ok(body.freeze())
+}
+
+/// Separates the parts of a composite dedup key
+pub const DEDUP_KEY_SEPARATOR: &str = ":";
+
+/// Build an event ID from the configured sources. A single source is used as is, while the
+/// parts of a composite key are escaped and joined with `:`, so `a:b` + `c` and `a` + `b:c` differ
+pub fn extract_dedup_key(req: &HttpRequest, sources: &[DedupKeySource], body: &Bytes) -> Result<String, Error> {
+    let body = Some(body.clone());
+    let mut parts = sources
+        .iter()
+        .map(|source| {
+            let value = extractors::extract_value(req, &source.get_in(), &source.get_locate(), &body, "Dedup key")?;
+            extractors::apply_pattern(value, source.get_pattern(), "Dedup key")
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if parts.len() == 1 {
+        return Ok(parts.remove(0));
+    }
+    let escaped: Vec<String> = parts.iter().map(|part| escape_key_part(part)).collect();
+    Ok(escaped.join(DEDUP_KEY_SEPARATOR))
+}
+
+/// Escape `\` and `:` in one part of a composite dedup key
+fn escape_key_part(part: &str) -> String {
+    part.replace('\\', "\\\\").replace(DEDUP_KEY_SEPARATOR, "\\:")
+}
+
+pub async fn data_receiver(
+    req: &HttpRequest,
+    body: Bytes,
+    alias: String,
+    delivery_id: Option<String>,
+    cache: web::Data<OrderedCache>,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    // Form, multipart and XML posts are stored as a JSON object with their Content-Type in the
+    // envelope, and JSON as is. Anything else is kept as text or bytes, marked as such in the envelope
+    let mut metadata = EventMetadata::from_request(req);
+    let content_type = req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or(body_format::APPLICATION_OCTET_STREAM)
+        .to_string();
+    let json_value = match body_format::parse_body(req, &body) {
+        Ok(value) => {
+            if body_format::converts_to_json(req) {
+                metadata.set_converted_body(content_type);
+            }
+            value
+        }
+        Err(e) => {
+            debug!("Storing body for alias {} as received: {}", alias, e);
+            metadata.set_raw_body(content_type, &body)
+        }
+    };
 
-    // Parse and validate it's valid JSON
-    let json_value: serde_json::Value = serde_json::from_slice(&body)?;
+    // Prefer the configured or provider's delivery ID so retries collapse, else hash the content
+    let key = match delivery_id {
+        Some(id) => id,
+        None => {
+            let mut hasher = Sha256::new();
+            hasher.update(&body);
+            let hash = hasher.finalize();
+            hex::encode(hash)
+        }
+    };
 
-    // Create hash of the content for the key
-    let mut hasher = Sha256::new();
-    hasher.update(&body);
-    let hash = hasher.finalize();
-    let key = hex::encode(hash);
+    // Record request metadata such as path parameters next to the body
+    let json_value = metadata.wrap(json_value);
 
     // Store the JSON value in cache
     cache.insert(&alias, key.clone(), json_value).await
-        .map_err(|e| Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+        .map_err(|e| Box::new(std::io::Error::other(e)))?;
 
     Ok((alias, key))
-}
\ No newline at end of file
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use actix_web::{HttpMessage, HttpRequest};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::body_format;
+use crate::client_cert::PeerCertificate;
+use crate::data_handler::data_config::EnvelopeConfig;
+use crate::jwt_auth::JwtClaims;
+use crate::verification_handler::extractors::path_params;
+
+/// Key holding request metadata in an enveloped event
+pub const ENVELOPE_KEY: &str = "_pollhook";
+
+// Define an enum for how a body that is not JSON is carried in the envelope's `body`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyEncoding {
+    Utf8,   // The body as a string
+    Base64, // The body's bytes, base64-encoded
+}
+
+/// Text of a body when it is valid UTF-8, or else its bytes in base64
+fn encode_body(body: &[u8]) -> (BodyEncoding, String) {
+    match std::str::from_utf8(body) {
+        Ok(text) => (BodyEncoding::Utf8, text.to_string()),
+        Err(_) => (BodyEncoding::Base64, BASE64.encode(body)),
+    }
+}
+
+/// Exact bytes of a request body, kept so pollers can check its signature again
+#[derive(Debug, Clone, Serialize)]
+pub struct RawBody {
+    encoding: BodyEncoding,
+    data: String,
+}
+
+/// Details of the HTTP request an event arrived in, recorded for aliases with an `envelope`
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestMetadata {
+    received_at: String,
+    method: String,
+    path: String,
+    query: Map<String, Value>, // Repeated parameters become arrays
+    source_ip: Option<String>,
+    headers: BTreeMap<String, String>, // Lowercased names, repeated headers joined with ", "
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_body: Option<RawBody>,
+}
+
+impl RequestMetadata {
+    pub fn new(
+        req: &HttpRequest,
+        config: &EnvelopeConfig,
+        received_at: DateTime<Utc>,
+        source_ip: Option<IpAddr>,
+        body: &[u8],
+    ) -> Self {
+        let query = match body_format::form_to_json(req.query_string().as_bytes()) {
+            Ok(Value::Object(query)) => query,
+            _ => Map::new(),
+        };
+
+        let mut headers: BTreeMap<String, String> = BTreeMap::new();
+        for (name, value) in req.headers().iter().filter(|(name, _)| config.records_header(name.as_str())) {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers.entry(name.to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+
+        let raw_body = config.keeps_raw_body().then(|| {
+            let (encoding, data) = encode_body(body);
+            RawBody { encoding, data }
+        });
+
+        Self {
+            received_at: received_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+            query,
+            source_ip: source_ip.map(|ip| ip.to_string()),
+            headers,
+            raw_body,
+        }
+    }
+}
+
+/// Request metadata cached next to a data event
+#[derive(Debug, Default, Serialize)]
+pub struct EventMetadata {
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    request: Option<RequestMetadata>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    params: BTreeMap<String, String>, // Named path segments, e.g. {tenant}
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    claims: BTreeMap<String, Value>, // Claims chosen from a verified bearer token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_cert_subject: Option<String>, // Subject of the client certificate the alias required
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>, // Content-Type of a body that did not arrive as JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<BodyEncoding>, // Set exactly when the body is not JSON
+}
+
+impl EventMetadata {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let extensions = req.extensions();
+        Self {
+            request: extensions.get::<RequestMetadata>().cloned(),
+            params: path_params(req),
+            claims: extensions.get::<JwtClaims>().map(|claims| claims.0.clone()).unwrap_or_default(),
+            client_cert_subject: extensions.get::<PeerCertificate>().map(PeerCertificate::get_subject),
+            content_type: None,
+            encoding: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.request.is_none()
+            && self.params.is_empty()
+            && self.claims.is_empty()
+            && self.client_cert_subject.is_none()
+            && self.content_type.is_none()
+            && self.encoding.is_none()
+    }
+
+    /// Record a body that could not be read as JSON, returning the value to store as `body`:
+    /// its text when it is valid UTF-8, or else its bytes in base64
+    pub fn set_raw_body(&mut self, content_type: String, body: &[u8]) -> Value {
+        let (encoding, value) = encode_body(body);
+
+        self.content_type = Some(content_type);
+        self.encoding = Some(encoding);
+        Value::String(value)
+    }
+
+    /// Record the Content-Type of a form, multipart or XML body that was converted to JSON
+    pub fn set_converted_body(&mut self, content_type: String) {
+        self.content_type = Some(content_type);
+    }
+
+    /// Wrap an event body as `{"_pollhook": {...}, "body": ...}`, or keep the bare body
+    /// when there is no metadata to record
+    pub fn wrap(self, body: Value) -> Value {
+        if self.is_empty() {
+            return body;
+        }
+
+        let mut envelope = serde_json::Map::new();
+        envelope.insert(ENVELOPE_KEY.to_string(), serde_json::to_value(self).unwrap_or_default());
+        envelope.insert("body".to_string(), body);
+        Value::Object(envelope)
+    }
+}
@@ -0,0 +1,37 @@
+use actix_web::{HttpRequest, HttpResponse, Error};
+use actix_web::http::StatusCode;
+use bytes::Bytes;
+use log::info;
+use crate::data_handler::data_config::InBandChallengeConfig;
+use crate::verification_handler::extractors;
+use crate::verification_handler::template::TemplateContext;
+use crate::verification_handler::verification::build_response;
+
+/// Answer an in-band verification challenge or ping, or return `None` when the request is a regular event
+pub fn answer_in_band_challenge(
+    req: &HttpRequest,
+    body: &Bytes,
+    config: &InBandChallengeConfig,
+) -> Result<Option<HttpResponse>, Error> {
+    let body = Some(body.clone());
+    let condition = config.get_condition();
+
+    // A request without the condition field, or whose field does not match the pattern, is not a challenge
+    let Ok(value) = extractors::extract_value(req, &condition.get_in(), &condition.get_locate(), &body, "Challenge condition")
+        .and_then(|value| extractors::apply_pattern(value, condition.get_pattern(), "Challenge condition")) else {
+        return Ok(None);
+    };
+
+    if condition.get_equals().is_some_and(|expected| expected != value) {
+        return Ok(None);
+    }
+
+    let challenge = match config.get_challenge_config() {
+        Some(challenge_config) => extractors::extract_challenge(req, challenge_config, &body)?,
+        None => String::new(),
+    };
+    info!("Answering in-band challenge on {}", req.path());
+
+    let context = TemplateContext::new(&challenge).with_request(req, body.as_ref());
+    build_response(config.get_response_config(), context, StatusCode::OK).map(Some)
+}
@@ -0,0 +1,39 @@
+use moka::future::Cache as MokaCache;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Remembers recently accepted signatures per alias so a captured request
+/// cannot be replayed while its timestamp is still inside the tolerance window.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayGuard {
+    seen: HashMap<String, Arc<MokaCache<String, ()>>>,
+}
+
+impl ReplayGuard {
+    pub fn new(windows: Vec<(String, Duration)>) -> Self {
+        let mut seen = HashMap::new();
+
+        for (alias, window) in windows {
+            // A timestamp up to the tolerance ahead of now stays acceptable for twice the window
+            let cache = Arc::new(
+                MokaCache::builder()
+                    .max_capacity(100_000)
+                    .time_to_live(window.saturating_mul(2))
+                    .build()
+            );
+            seen.insert(alias, cache);
+        }
+
+        Self { seen }
+    }
+
+    /// Record the replay key of a verified request, returning false when it was already seen for this alias
+    pub async fn remember(&self, alias: &str, key: &str) -> bool {
+        match self.seen.get(alias) {
+            Some(cache) => cache.entry(key.to_string()).or_insert(()).await.is_fresh(),
+            // Aliases without a replay window are not tracked
+            None => true,
+        }
+    }
+}
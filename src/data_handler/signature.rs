@@ -0,0 +1,290 @@
+use actix_web::{HttpRequest, Error};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::{Hmac, KeyInit, Mac};
+use log::error;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use crate::body_format;
+use crate::data_handler::replay_guard::ReplayGuard;
+use crate::data_handler::signature_config::{
+    SignatureAlgorithm, SignatureConfig, SignatureEncoding, SignatureScheme, TimestampConfig,
+    STANDARD_WEBHOOKS_ID_HEADER, STANDARD_WEBHOOKS_SIGNATURE_HEADER, STANDARD_WEBHOOKS_TIMESTAMP_HEADER,
+    TWILIO_SIGNATURE_HEADER,
+};
+
+/// Compute the HMAC of `message` keyed with `secret`
+pub fn hmac_digest(algorithm: SignatureAlgorithm, secret: &[u8], message: &[u8]) -> Vec<u8> {
+    match algorithm {
+        SignatureAlgorithm::HmacSha1 => mac_bytes::<Hmac<Sha1>>(secret, message),
+        SignatureAlgorithm::HmacSha256 => mac_bytes::<Hmac<Sha256>>(secret, message),
+        SignatureAlgorithm::HmacSha512 => mac_bytes::<Hmac<Sha512>>(secret, message),
+    }
+}
+
+/// Check `expected` against the HMAC of `message` in constant time
+pub fn hmac_matches(algorithm: SignatureAlgorithm, secret: &[u8], message: &[u8], expected: &[u8]) -> bool {
+    match algorithm {
+        SignatureAlgorithm::HmacSha1 => mac_verify::<Hmac<Sha1>>(secret, message, expected),
+        SignatureAlgorithm::HmacSha256 => mac_verify::<Hmac<Sha256>>(secret, message, expected),
+        SignatureAlgorithm::HmacSha512 => mac_verify::<Hmac<Sha512>>(secret, message, expected),
+    }
+}
+
+fn mac_bytes<M: Mac + KeyInit>(secret: &[u8], message: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn mac_verify<M: Mac + KeyInit>(secret: &[u8], message: &[u8], expected: &[u8]) -> bool {
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.verify_slice(expected).is_ok()
+}
+
+/// Decode a signature sent as hex or base64
+pub fn decode_signature(encoding: SignatureEncoding, value: &str) -> Option<Vec<u8>> {
+    match encoding {
+        SignatureEncoding::Hex => hex::decode(value.trim()).ok(),
+        SignatureEncoding::Base64 => BASE64.decode(value.trim()).ok(),
+    }
+}
+
+/// Split a structured header such as `t=1700000000,v1=abc,v1=def` into key/value pairs
+pub fn parse_structured_header(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Build the signed message from the configured template, keeping the body as raw bytes
+fn build_signed_message(template: &str, timestamp: Option<&str>, body: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(template.len() + body.len());
+    let mut parts = template.split("{body}").peekable();
+
+    while let Some(part) = parts.next() {
+        message.extend_from_slice(part.replace("{timestamp}", timestamp.unwrap_or_default()).as_bytes());
+        if parts.peek().is_some() {
+            message.extend_from_slice(body);
+        }
+    }
+
+    message
+}
+
+fn header_str<'a>(req: &'a HttpRequest, name: &str) -> Result<&'a str, Error> {
+    req.headers()
+        .get(name)
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| {
+            error!("Signature header missing: {}", name);
+            actix_web::error::ErrorUnauthorized("Missing signature")
+        })
+}
+
+/// Read the signing timestamp and reject it when it is outside the tolerance window
+fn check_timestamp(
+    req: &HttpRequest,
+    pairs: &[(String, String)],
+    config: &TimestampConfig,
+) -> Result<String, Error> {
+    let timestamp = if let Some(key) = config.get_key() {
+        pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| v.clone())
+    } else if let Some(header) = config.get_header() {
+        header_str(req, &header).ok().map(|v| v.trim().to_string())
+    } else {
+        None
+    };
+
+    let timestamp = timestamp.ok_or_else(|| {
+        error!("Signature timestamp missing");
+        actix_web::error::ErrorUnauthorized("Missing signature timestamp")
+    })?;
+
+    check_timestamp_drift(&timestamp, config.get_tolerance_seconds())?;
+    Ok(timestamp)
+}
+
+fn check_timestamp_drift(timestamp: &str, tolerance_seconds: u64) -> Result<(), Error> {
+    let seconds = timestamp.parse::<i64>().map_err(|_| {
+        error!("Signature timestamp is not a unix timestamp: {}", timestamp);
+        actix_web::error::ErrorUnauthorized("Invalid signature timestamp")
+    })?;
+
+    let drift = (chrono::Utc::now().timestamp() - seconds).unsigned_abs();
+    if drift > tolerance_seconds {
+        error!("Signature timestamp outside tolerance: {}s drift", drift);
+        return Err(actix_web::error::ErrorUnauthorized("Signature timestamp outside tolerance"));
+    }
+
+    Ok(())
+}
+
+/// Verify a request signed per the Standard Webhooks specification.
+///
+/// The signed content is `{webhook-id}.{webhook-timestamp}.{body}`, and `webhook-signature`
+/// holds one or more space-separated `v1,<base64>` entries.
+fn verify_standard_webhooks(
+    req: &HttpRequest,
+    body: &[u8],
+    config: &SignatureConfig,
+) -> Result<String, Error> {
+    let id = header_str(req, STANDARD_WEBHOOKS_ID_HEADER)?;
+    let timestamp = header_str(req, STANDARD_WEBHOOKS_TIMESTAMP_HEADER)?.trim();
+    let signatures = header_str(req, STANDARD_WEBHOOKS_SIGNATURE_HEADER)?;
+
+    let tolerance = config.get_replay_window_seconds().unwrap_or_default();
+    check_timestamp_drift(timestamp, tolerance)?;
+
+    let mut message = format!("{}.{}.", id, timestamp).into_bytes();
+    message.extend_from_slice(body);
+
+    let matched = signatures
+        .split_whitespace()
+        .filter_map(|entry| entry.strip_prefix("v1,"))
+        .filter_map(|signature| decode_signature(SignatureEncoding::Base64, signature))
+        .find(|expected| hmac_matches(SignatureAlgorithm::HmacSha256, config.get_secret(), &message, expected));
+
+    let Some(expected) = matched else {
+        error!("Standard Webhooks signature mismatch for webhook-id {}", id);
+        return Err(actix_web::error::ErrorUnauthorized("Invalid signature"));
+    };
+
+    // A retry is re-signed with a new timestamp, so it is not mistaken for a replay
+    Ok(format!("{}:{}", id, hex::encode(expected)))
+}
+
+/// Full URL the provider called, using the configured public base URL when behind a proxy
+fn public_request_url(req: &HttpRequest, public_base_url: Option<String>) -> String {
+    let path_and_query = req.uri()
+        .path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| req.path().to_string());
+
+    match public_base_url {
+        Some(base) => format!("{}{}", base.trim_end_matches('/'), path_and_query),
+        None => {
+            let connection = req.connection_info();
+            format!("{}://{}{}", connection.scheme(), connection.host(), path_and_query)
+        }
+    }
+}
+
+/// Verify a request signed the way Twilio does it.
+///
+/// The signed string is the full public URL followed by every POST form parameter,
+/// sorted by name, with each name and value appended without separators.
+fn verify_twilio(
+    req: &HttpRequest,
+    body: &[u8],
+    config: &SignatureConfig,
+) -> Result<String, Error> {
+    let signature = header_str(req, TWILIO_SIGNATURE_HEADER)?;
+
+    let mut message = public_request_url(req, config.get_public_base_url());
+    if body_format::media_type(req) == body_format::FORM_URLENCODED {
+        let mut params = body_format::parse_form_pairs(body).map_err(|e| {
+            error!("Failed to parse form body for Twilio signature: {}", e);
+            actix_web::error::ErrorUnauthorized("Invalid signature")
+        })?;
+        params.sort();
+        for (key, value) in params {
+            message.push_str(&key);
+            message.push_str(&value);
+        }
+    }
+
+    let matched = decode_signature(SignatureEncoding::Base64, signature)
+        .filter(|expected| hmac_matches(SignatureAlgorithm::HmacSha1, config.get_secret(), message.as_bytes(), expected));
+
+    let Some(expected) = matched else {
+        error!("Twilio signature mismatch for {}", req.path());
+        return Err(actix_web::error::ErrorUnauthorized("Invalid signature"));
+    };
+
+    Ok(hex::encode(expected))
+}
+
+/// Check one decoded signature against the alias's shared secret or public key
+fn signature_matches(config: &SignatureConfig, message: &[u8], expected: &[u8]) -> bool {
+    match config.get_verifying_key() {
+        Some(key) => key.verify(message, expected),
+        None if config.get_scheme().uses_public_key() => false,
+        None => hmac_matches(config.get_algorithm(), config.get_secret(), message, expected),
+    }
+}
+
+/// Verify the signature header of a data request against its raw body.
+///
+/// Returns the key the replay guard remembers: the signed timestamp and the decoded signature
+/// that matched, so rewording the header around a captured signature does not make it new.
+pub fn verify_signature(
+    req: &HttpRequest,
+    body: &[u8],
+    config: &SignatureConfig,
+) -> Result<String, Error> {
+    match config.get_scheme() {
+        SignatureScheme::StandardWebhooks => return verify_standard_webhooks(req, body, config),
+        SignatureScheme::Twilio => return verify_twilio(req, body, config),
+        SignatureScheme::Hmac | SignatureScheme::Ed25519 | SignatureScheme::EcdsaP256 => {}
+    }
+
+    let header_name = config.get_header();
+    let header_value = header_str(req, &header_name)?;
+
+    // Structured headers carry the signature (possibly several) next to other fields
+    let pairs = match config.get_signature_key() {
+        Some(_) => parse_structured_header(header_value),
+        None => Vec::new(),
+    };
+    let candidates: Vec<&str> = match config.get_signature_key() {
+        Some(key) => pairs.iter().filter(|(k, _)| *k == key).map(|(_, v)| v.as_str()).collect(),
+        None => vec![header_value],
+    };
+
+    let timestamp = match config.get_timestamp_config() {
+        Some(timestamp_config) => Some(check_timestamp(req, &pairs, timestamp_config)?),
+        None => None,
+    };
+
+    let message = build_signed_message(&config.get_template(), timestamp.as_deref(), body);
+    let prefix = config.get_prefix().unwrap_or_default();
+
+    let matched = candidates.iter().find_map(|candidate| {
+        candidate
+            .strip_prefix(prefix.as_str())
+            .and_then(|signature| decode_signature(config.get_encoding(), signature))
+            .filter(|expected| signature_matches(config, &message, expected))
+    });
+
+    let Some(expected) = matched else {
+        error!("Signature mismatch for header {}", header_name);
+        return Err(actix_web::error::ErrorUnauthorized("Invalid signature"));
+    };
+
+    Ok(format!("{}:{}", timestamp.unwrap_or_default(), hex::encode(expected)))
+}
+
+/// Verify a signed data request and, for timestamped signatures, refuse replays inside the window
+pub async fn verify_signed_request(
+    req: &HttpRequest,
+    body: &[u8],
+    alias: &str,
+    config: &SignatureConfig,
+    replay_guard: &ReplayGuard,
+) -> Result<(), Error> {
+    let replay_key = verify_signature(req, body, config)?;
+
+    if config.get_replay_window_seconds().is_some() && !replay_guard.remember(alias, &replay_key).await {
+        error!("Replayed signature rejected for alias {}", alias);
+        return Err(actix_web::error::ErrorUnauthorized("Replayed request"));
+    }
+
+    Ok(())
+}
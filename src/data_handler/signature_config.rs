@@ -0,0 +1,345 @@
+use std::env;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Deserializer};
+
+/// Headers and secret prefix fixed by the Standard Webhooks specification
+pub const STANDARD_WEBHOOKS_ID_HEADER: &str = "webhook-id";
+pub const STANDARD_WEBHOOKS_TIMESTAMP_HEADER: &str = "webhook-timestamp";
+pub const STANDARD_WEBHOOKS_SIGNATURE_HEADER: &str = "webhook-signature";
+const STANDARD_WEBHOOKS_SECRET_PREFIX: &str = "whsec_";
+
+/// Header carrying Twilio's request signature
+pub const TWILIO_SIGNATURE_HEADER: &str = "X-Twilio-Signature";
+
+// Define an enum for the signature verification schemes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureScheme {
+    Hmac,
+    StandardWebhooks,
+    Twilio,
+    Ed25519,   // Public-key signature, e.g. Discord interactions
+    EcdsaP256, // ECDSA over P-256 with SHA-256, e.g. SendGrid event webhooks
+}
+
+impl SignatureScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignatureScheme::Hmac => "hmac",
+            SignatureScheme::StandardWebhooks => "standard_webhooks",
+            SignatureScheme::Twilio => "twilio",
+            SignatureScheme::Ed25519 => "ed25519",
+            SignatureScheme::EcdsaP256 => "ecdsa-p256",
+        }
+    }
+
+    /// Schemes checked against a configured public key rather than a shared secret
+    pub fn uses_public_key(&self) -> bool {
+        matches!(self, SignatureScheme::Ed25519 | SignatureScheme::EcdsaP256)
+    }
+}
+
+// Custom deserialization for SignatureScheme
+impl<'de> Deserialize<'de> for SignatureScheme {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "hmac" => Ok(SignatureScheme::Hmac),
+            "standard_webhooks" => Ok(SignatureScheme::StandardWebhooks),
+            "twilio" => Ok(SignatureScheme::Twilio),
+            "ed25519" => Ok(SignatureScheme::Ed25519),
+            "ecdsa-p256" => Ok(SignatureScheme::EcdsaP256),
+            _ => Err(serde::de::Error::custom(format!(
+                "Invalid signature scheme: {}. Expected 'hmac', 'standard_webhooks', 'twilio', 'ed25519' or 'ecdsa-p256'",
+                s
+            ))),
+        }
+    }
+}
+
+// Define an enum for the supported signature algorithms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureAlgorithm {
+    HmacSha1,
+    HmacSha256,
+    HmacSha512,
+}
+
+// Custom deserialization for SignatureAlgorithm
+impl<'de> Deserialize<'de> for SignatureAlgorithm {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "hmac-sha1" => Ok(SignatureAlgorithm::HmacSha1),
+            "hmac-sha256" => Ok(SignatureAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(SignatureAlgorithm::HmacSha512),
+            _ => Err(serde::de::Error::custom(format!(
+                "Invalid signature algorithm: {}. Expected 'hmac-sha1', 'hmac-sha256' or 'hmac-sha512'",
+                s
+            ))),
+        }
+    }
+}
+
+// Define an enum for the encodings a signature can be sent in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+}
+
+// Custom deserialization for SignatureEncoding
+impl<'de> Deserialize<'de> for SignatureEncoding {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(SignatureEncoding::Hex),
+            "base64" => Ok(SignatureEncoding::Base64),
+            _ => Err(serde::de::Error::custom(format!(
+                "Invalid signature encoding: {}. Expected 'hex' or 'base64'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Public key a signature is checked against
+#[derive(Debug, Clone)]
+pub enum VerifyingKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    EcdsaP256(p256::ecdsa::VerifyingKey),
+}
+
+impl VerifyingKey {
+    /// Parse a PEM public key, or raw key bytes in hex or base64 (32 bytes for Ed25519,
+    /// a SEC1 point or DER SubjectPublicKeyInfo for P-256)
+    pub fn parse(scheme: SignatureScheme, value: &str) -> Result<Self, String> {
+        use ed25519_dalek::pkcs8::DecodePublicKey;
+
+        let value = value.trim();
+        let invalid = |e: &dyn std::fmt::Display| format!("Invalid {} public key: {}", scheme.as_str(), e);
+
+        if value.starts_with("-----BEGIN") {
+            return match scheme {
+                SignatureScheme::Ed25519 => ed25519_dalek::VerifyingKey::from_public_key_pem(value)
+                    .map(VerifyingKey::Ed25519).map_err(|e| invalid(&e)),
+                _ => p256::ecdsa::VerifyingKey::from_public_key_pem(value)
+                    .map(VerifyingKey::EcdsaP256).map_err(|e| invalid(&e)),
+            };
+        }
+
+        let bytes = hex::decode(value)
+            .or_else(|_| BASE64.decode(value))
+            .map_err(|_| invalid(&"expected hex, base64 or PEM"))?;
+
+        match scheme {
+            SignatureScheme::Ed25519 => match <[u8; 32]>::try_from(bytes.as_slice()) {
+                Ok(raw) => ed25519_dalek::VerifyingKey::from_bytes(&raw).map_err(|e| invalid(&e)),
+                Err(_) => ed25519_dalek::VerifyingKey::from_public_key_der(&bytes).map_err(|e| invalid(&e)),
+            }.map(VerifyingKey::Ed25519),
+            _ => p256::ecdsa::VerifyingKey::from_sec1_bytes(&bytes).ok()
+                .or_else(|| p256::ecdsa::VerifyingKey::from_public_key_der(&bytes).ok())
+                .map(VerifyingKey::EcdsaP256)
+                .ok_or_else(|| invalid(&"expected a SEC1 point or DER SubjectPublicKeyInfo")),
+        }
+    }
+
+    /// Check a signature over `message`; P-256 signatures may be DER or fixed-size `r || s`
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        use p256::ecdsa::signature::Verifier;
+
+        match self {
+            VerifyingKey::Ed25519(key) => ed25519_dalek::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify_strict(message, &signature).is_ok()),
+            VerifyingKey::EcdsaP256(key) => p256::ecdsa::Signature::from_der(signature)
+                .or_else(|_| p256::ecdsa::Signature::from_slice(signature))
+                .is_ok_and(|signature| key.verify(message, &signature).is_ok()),
+        }
+    }
+}
+
+fn default_tolerance_seconds() -> u64 {
+    300
+}
+
+/// Where the signing timestamp is read from, and how far it may drift from now
+#[derive(Debug, Deserialize, Clone)]
+pub struct TimestampConfig {
+    #[serde(default)]
+    header: Option<String>, // Separate header, e.g. X-Slack-Request-Timestamp
+    #[serde(default)]
+    key: Option<String>, // Key inside a structured signature header, e.g. "t" for Stripe
+    #[serde(default = "default_tolerance_seconds")]
+    tolerance_seconds: u64,
+}
+
+impl TimestampConfig {
+    pub fn get_header(&self) -> Option<String> {
+        self.header.clone()
+    }
+
+    pub fn get_key(&self) -> Option<String> {
+        self.key.clone()
+    }
+
+    pub fn get_tolerance_seconds(&self) -> u64 {
+        self.tolerance_seconds
+    }
+}
+
+fn default_template() -> String {
+    "{body}".to_string()
+}
+
+fn default_scheme() -> SignatureScheme {
+    SignatureScheme::Hmac
+}
+
+fn default_algorithm() -> SignatureAlgorithm {
+    SignatureAlgorithm::HmacSha256
+}
+
+fn default_encoding() -> SignatureEncoding {
+    SignatureEncoding::Hex
+}
+
+/// Payload signature check for a data alias, run over the raw body before it is cached
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignatureConfig {
+    #[serde(default = "default_scheme")]
+    scheme: SignatureScheme,
+    #[serde(default)]
+    header: String, // Not needed by schemes with fixed headers
+    #[serde(default = "default_algorithm")]
+    algorithm: SignatureAlgorithm,
+    #[serde(default = "default_encoding")]
+    encoding: SignatureEncoding,
+    #[serde(default)]
+    prefix: Option<String>, // e.g. "sha256=" for X-Hub-Signature-256
+    #[serde(default)]
+    secret_env: String, // Not needed by public-key schemes
+    #[serde(skip)]
+    secret: Vec<u8>,
+    #[serde(default)]
+    public_key: Option<String>, // Hex, base64 or PEM public key for the ed25519 and ecdsa-p256 schemes
+    #[serde(skip)]
+    verifying_key: Option<VerifyingKey>,
+    #[serde(default)]
+    signature_key: Option<String>, // Set for structured headers like "t=1700000000,v1=abc"
+    #[serde(default)]
+    timestamp: Option<TimestampConfig>,
+    #[serde(default = "default_template")]
+    template: String, // Signed string, built from {timestamp} and {body}
+    #[serde(default)]
+    public_base_url: Option<String>, // Public scheme and host the provider signed, when behind a proxy
+}
+
+impl SignatureConfig {
+    pub fn get_scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+
+    pub fn get_header(&self) -> String {
+        match self.scheme {
+            SignatureScheme::Hmac | SignatureScheme::Ed25519 | SignatureScheme::EcdsaP256 => self.header.clone(),
+            SignatureScheme::StandardWebhooks => STANDARD_WEBHOOKS_SIGNATURE_HEADER.to_string(),
+            SignatureScheme::Twilio => TWILIO_SIGNATURE_HEADER.to_string(),
+        }
+    }
+
+    pub fn get_public_base_url(&self) -> Option<String> {
+        self.public_base_url.clone()
+    }
+
+    /// Header whose value identifies a delivery, so provider retries collapse onto one cache key
+    pub fn get_dedup_header(&self) -> Option<&'static str> {
+        match self.scheme {
+            SignatureScheme::StandardWebhooks => Some(STANDARD_WEBHOOKS_ID_HEADER),
+            _ => None,
+        }
+    }
+
+    /// Seconds a signature stays acceptable, when the scheme signs a timestamp
+    pub fn get_replay_window_seconds(&self) -> Option<u64> {
+        match (self.scheme, &self.timestamp) {
+            (_, Some(timestamp)) => Some(timestamp.get_tolerance_seconds()),
+            (SignatureScheme::StandardWebhooks, None) => Some(default_tolerance_seconds()),
+            (_, None) => None,
+        }
+    }
+
+    pub fn get_algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm
+    }
+
+    pub fn get_encoding(&self) -> SignatureEncoding {
+        self.encoding
+    }
+
+    pub fn get_prefix(&self) -> Option<String> {
+        self.prefix.clone()
+    }
+
+    pub fn get_signature_key(&self) -> Option<String> {
+        self.signature_key.clone()
+    }
+
+    pub fn get_timestamp_config(&self) -> Option<&TimestampConfig> {
+        self.timestamp.as_ref()
+    }
+
+    pub fn get_template(&self) -> String {
+        self.template.clone()
+    }
+
+    pub fn get_secret_env(&self) -> String {
+        self.secret_env.clone()
+    }
+
+    pub fn get_secret(&self) -> &[u8] {
+        &self.secret
+    }
+
+    /// Set the shared secret as it is written in `secret_env`
+    pub fn set_secret(&mut self, secret: &str) -> Result<(), String> {
+        // Standard Webhooks secrets are base64, optionally prefixed with "whsec_"
+        self.secret = if self.scheme == SignatureScheme::StandardWebhooks {
+            let encoded = secret.strip_prefix(STANDARD_WEBHOOKS_SECRET_PREFIX).unwrap_or(secret);
+            BASE64.decode(encoded.trim())
+                .map_err(|_| format!("{} is not a valid base64 secret", self.secret_env))?
+        } else {
+            secret.as_bytes().to_vec()
+        };
+        Ok(())
+    }
+
+    pub fn get_public_key(&self) -> Option<String> {
+        self.public_key.clone()
+    }
+
+    pub fn get_verifying_key(&self) -> Option<&VerifyingKey> {
+        self.verifying_key.as_ref()
+    }
+
+    /// Read the shared secret from `secret_env`, or parse `public_key` for public-key schemes
+    pub fn load_secret(&mut self) -> Result<(), String> {
+        if self.scheme.uses_public_key() {
+            let public_key = self.public_key.as_deref().ok_or("public_key is not set")?;
+            self.verifying_key = Some(VerifyingKey::parse(self.scheme, public_key)?);
+            return Ok(());
+        }
+
+        let secret = env::var(&self.secret_env)
+            .map_err(|_| format!("{} is not set", self.secret_env))?;
+        self.set_secret(&secret)
+    }
+}
@@ -0,0 +1,1164 @@
+#[allow(unused_imports)]
+use super::*;
+
+/// Signature config with `secret` set as if it had been read from its `secret_env`
+#[cfg(test)]
+fn create_signature_config(config_json: serde_json::Value, secret: &str) -> signature_config::SignatureConfig {
+    let mut config: signature_config::SignatureConfig = serde_json::from_value(config_json).unwrap();
+    config.set_secret(secret).unwrap();
+    config
+}
+
+/// Signature config of a public-key scheme with its `public_key` parsed
+#[cfg(test)]
+fn create_public_key_config(config_json: serde_json::Value) -> signature_config::SignatureConfig {
+    let mut config: signature_config::SignatureConfig = serde_json::from_value(config_json).unwrap();
+    config.load_secret().unwrap();
+    config
+}
+
+/// App that hands every POST to the data endpoint handler of `alias`, configured from `config_yaml`
+#[cfg(test)]
+fn data_app(
+    config_yaml: &str,
+    alias: &str,
+    cache: actix_web::web::Data<crate::cache::OrderedCache>,
+) -> actix_web::App<impl actix_web::dev::ServiceFactory<
+    actix_web::dev::ServiceRequest,
+    Config = (),
+    Response = actix_web::dev::ServiceResponse,
+    Error = actix_web::Error,
+    InitError = (),
+> + use<>> {
+    let config: crate::webhook_config::WebhookConfig = serde_yaml::from_str(config_yaml).unwrap();
+    let replay_guard = replay_guard::ReplayGuard::new(config.get_data_config().get_replay_windows());
+    let alias = alias.to_string();
+    actix_web::App::new()
+        .app_data(actix_web::web::Data::new(config))
+        .app_data(cache)
+        .app_data(actix_web::web::Data::new(replay_guard))
+        .route("/{path:.*}", actix_web::web::post().to(
+            move |req, payload, config, cache, replay_guard| {
+                crate::endpoint_handler::data_endpoint_handler(req, payload, alias.clone(), config, cache, replay_guard)
+            }
+        ))
+}
+
+#[cfg(test)]
+mod test_signature {
+    use super::create_signature_config;
+    use super::signature::*;
+    use super::signature_config::{SignatureAlgorithm, SignatureConfig};
+
+    use actix_web::test;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use serde_json::json;
+
+    // Example from GitHub's webhook documentation
+    const GITHUB_SECRET: &str = "It's a Secret to Everybody";
+    const GITHUB_PAYLOAD: &str = "Hello, World!";
+    const GITHUB_SIGNATURE: &str = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    fn github_config() -> SignatureConfig {
+        create_signature_config(json!({
+            "header": "X-Hub-Signature-256",
+            "algorithm": "hmac-sha256",
+            "encoding": "hex",
+            "prefix": "sha256=",
+            "secret_env": "GITHUB_WEBHOOK_SECRET"
+        }), GITHUB_SECRET)
+    }
+
+    #[actix_web::test]
+    async fn test_hex_signature_with_prefix() {
+        let config = github_config();
+
+        let req = test::TestRequest::default()
+            .insert_header(("X-Hub-Signature-256", GITHUB_SIGNATURE))
+            .to_http_request();
+        assert!(verify_signature(&req, GITHUB_PAYLOAD.as_bytes(), &config).is_ok());
+
+        // Tampered body
+        assert!(verify_signature(&req, b"Hello, World?", &config).is_err());
+
+        // Missing prefix
+        let req = test::TestRequest::default()
+            .insert_header(("X-Hub-Signature-256", &GITHUB_SIGNATURE[7..]))
+            .to_http_request();
+        assert!(verify_signature(&req, GITHUB_PAYLOAD.as_bytes(), &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_missing_or_malformed_header() {
+        let config = github_config();
+
+        let req = test::TestRequest::default().to_http_request();
+        assert!(verify_signature(&req, GITHUB_PAYLOAD.as_bytes(), &config).is_err());
+
+        let req = test::TestRequest::default()
+            .insert_header(("X-Hub-Signature-256", "sha256=not-hex"))
+            .to_http_request();
+        assert!(verify_signature(&req, GITHUB_PAYLOAD.as_bytes(), &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_base64_signature() {
+        // Shopify style: base64 HMAC-SHA256 without prefix
+        let config = create_signature_config(json!({
+            "header": "X-Shopify-Hmac-Sha256",
+            "encoding": "base64",
+            "secret_env": "SHOPIFY_SECRET"
+        }), "shopify-secret");
+
+        let body = br#"{"id":1}"#;
+        let signature = BASE64.encode(hmac_digest(SignatureAlgorithm::HmacSha256, b"shopify-secret", body));
+
+        let req = test::TestRequest::default()
+            .insert_header(("X-Shopify-Hmac-Sha256", signature.as_str()))
+            .to_http_request();
+        assert!(verify_signature(&req, body, &config).is_ok());
+
+        // Wrong secret
+        let config = create_signature_config(json!({
+            "header": "X-Shopify-Hmac-Sha256",
+            "encoding": "base64",
+            "secret_env": "SHOPIFY_SECRET"
+        }), "other-secret");
+        assert!(verify_signature(&req, body, &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_sha1_and_sha512() {
+        let body = br#"{"event":"push"}"#;
+
+        for (algorithm, name) in [(SignatureAlgorithm::HmacSha1, "hmac-sha1"), (SignatureAlgorithm::HmacSha512, "hmac-sha512")] {
+            let config = create_signature_config(json!({
+                "header": "X-Signature",
+                "algorithm": name,
+                "secret_env": "SECRET"
+            }), "secret");
+
+            let signature = hex::encode(hmac_digest(algorithm, b"secret", body));
+            let req = test::TestRequest::default()
+                .insert_header(("X-Signature", signature.as_str()))
+                .to_http_request();
+            assert!(verify_signature(&req, body, &config).is_ok());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_algorithm_and_encoding() {
+        let result = serde_json::from_value::<SignatureConfig>(json!({
+            "header": "X-Signature",
+            "algorithm": "md5",
+            "secret_env": "SECRET"
+        }));
+        assert!(result.is_err());
+
+        let result = serde_json::from_value::<SignatureConfig>(json!({
+            "header": "X-Signature",
+            "encoding": "base32",
+            "secret_env": "SECRET"
+        }));
+        assert!(result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_timestamped_signature {
+    use super::create_signature_config;
+    use super::replay_guard::ReplayGuard;
+    use super::signature::*;
+    use super::signature_config::{SignatureAlgorithm, SignatureConfig};
+
+    use actix_web::test;
+    use serde_json::json;
+    use std::time::Duration;
+
+    const BODY: &[u8] = br#"{"type":"event_callback"}"#;
+
+    fn stripe_config() -> SignatureConfig {
+        create_signature_config(json!({
+            "header": "Stripe-Signature",
+            "signature_key": "v1",
+            "timestamp": { "key": "t", "tolerance_seconds": 300 },
+            "template": "{timestamp}.{body}",
+            "secret_env": "STRIPE_SECRET"
+        }), "whsec_test")
+    }
+
+    fn sign(message: &[u8]) -> String {
+        hex::encode(hmac_digest(SignatureAlgorithm::HmacSha256, b"whsec_test", message))
+    }
+
+    fn stripe_header(timestamp: i64) -> String {
+        let mut message = format!("{}.", timestamp).into_bytes();
+        message.extend_from_slice(BODY);
+        format!("t={},v1={},v0=ignored", timestamp, sign(&message))
+    }
+
+    #[actix_web::test]
+    async fn test_parse_structured_header() {
+        let pairs = parse_structured_header("t=1700000000, v1=abc,v1=def,broken");
+        assert_eq!(pairs, vec![
+            ("t".to_string(), "1700000000".to_string()),
+            ("v1".to_string(), "abc".to_string()),
+            ("v1".to_string(), "def".to_string()),
+        ]);
+    }
+
+    #[actix_web::test]
+    async fn test_stripe_style_signature() {
+        let config = stripe_config();
+        let now = chrono::Utc::now().timestamp();
+
+        let req = test::TestRequest::default()
+            .insert_header(("Stripe-Signature", stripe_header(now)))
+            .to_http_request();
+        assert!(verify_signature(&req, BODY, &config).is_ok());
+
+        // Any of several v1 signatures may match
+        let mut message = format!("{}.", now).into_bytes();
+        message.extend_from_slice(BODY);
+        let header = format!("t={},v1=00ff,v1={}", now, sign(&message));
+        let req = test::TestRequest::default()
+            .insert_header(("Stripe-Signature", header))
+            .to_http_request();
+        assert!(verify_signature(&req, BODY, &config).is_ok());
+
+        // Tampered body
+        let req = test::TestRequest::default()
+            .insert_header(("Stripe-Signature", stripe_header(now)))
+            .to_http_request();
+        assert!(verify_signature(&req, b"{}", &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_timestamp_outside_tolerance() {
+        let config = stripe_config();
+        let now = chrono::Utc::now().timestamp();
+
+        for timestamp in [now - 301, now + 301] {
+            let req = test::TestRequest::default()
+                .insert_header(("Stripe-Signature", stripe_header(timestamp)))
+                .to_http_request();
+            assert!(verify_signature(&req, BODY, &config).is_err());
+        }
+
+        // Missing timestamp
+        let req = test::TestRequest::default()
+            .insert_header(("Stripe-Signature", format!("v1={}", sign(BODY))))
+            .to_http_request();
+        assert!(verify_signature(&req, BODY, &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_slack_style_signature() {
+        let config = create_signature_config(json!({
+            "header": "X-Slack-Signature",
+            "prefix": "v0=",
+            "timestamp": { "header": "X-Slack-Request-Timestamp" },
+            "template": "v0:{timestamp}:{body}",
+            "secret_env": "SLACK_SIGNING_SECRET"
+        }), "whsec_test");
+        let now = chrono::Utc::now().timestamp();
+
+        let mut message = format!("v0:{}:", now).into_bytes();
+        message.extend_from_slice(BODY);
+        let signature = format!("v0={}", sign(&message));
+
+        let req = test::TestRequest::default()
+            .insert_header(("X-Slack-Signature", signature.as_str()))
+            .insert_header(("X-Slack-Request-Timestamp", now.to_string()))
+            .to_http_request();
+        assert!(verify_signature(&req, BODY, &config).is_ok());
+
+        // Timestamp header changed after signing
+        let req = test::TestRequest::default()
+            .insert_header(("X-Slack-Signature", signature.as_str()))
+            .insert_header(("X-Slack-Request-Timestamp", (now - 1).to_string()))
+            .to_http_request();
+        assert!(verify_signature(&req, BODY, &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_replayed_request_rejected() {
+        let config = stripe_config();
+        let guard = ReplayGuard::new(vec![("stripe".to_string(), Duration::from_secs(300))]);
+        let header = stripe_header(chrono::Utc::now().timestamp());
+
+        let req = test::TestRequest::default()
+            .insert_header(("Stripe-Signature", header.as_str()))
+            .to_http_request();
+
+        assert!(verify_signed_request(&req, BODY, "stripe", &config, &guard).await.is_ok());
+        assert!(verify_signed_request(&req, BODY, "stripe", &config, &guard).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_reworded_replay_rejected() {
+        let config = stripe_config();
+        let guard = ReplayGuard::new(vec![("stripe".to_string(), Duration::from_secs(300))]);
+        let now = chrono::Utc::now().timestamp();
+        let header = stripe_header(now);
+        let mut message = format!("{}.", now).into_bytes();
+        message.extend_from_slice(BODY);
+        let signature = sign(&message);
+
+        let send = |header: String| test::TestRequest::default()
+            .insert_header(("Stripe-Signature", header))
+            .to_http_request();
+        assert!(verify_signed_request(&send(header.clone()), BODY, "stripe", &config, &guard).await.is_ok());
+
+        // The captured signature still verifies inside a reworded header, but is the same delivery
+        for reworded in [
+            format!("{},v0=x", header),
+            header.replace("v0=ignored", "v0=other"),
+            header.replace(&signature, &signature.to_uppercase()),
+        ] {
+            assert!(verify_signature(&send(reworded.clone()), BODY, &config).is_ok());
+            assert!(verify_signed_request(&send(reworded), BODY, "stripe", &config, &guard).await.is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_standard_webhooks {
+    use super::create_signature_config;
+    use super::data_receiver::data_receiver;
+    use super::replay_guard::ReplayGuard;
+    use super::signature::*;
+    use super::signature_config::{SignatureAlgorithm, SignatureConfig};
+    use crate::cache::OrderedCache;
+
+    use actix_web::{test, web};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use bytes::Bytes;
+    use serde_json::json;
+    use std::time::Duration;
+
+    // Test vector from the Standard Webhooks specification
+    const SECRET: &str = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+    const MSG_ID: &str = "msg_p5jXN8AQM9LWM0D4loKWxJek";
+    const TIMESTAMP: &str = "1614265330";
+    const PAYLOAD: &str = r#"{"test": 2432232314}"#;
+    const SIGNATURE: &str = "v1,g0hM9SsE+OTPJTGt/tmIKtSyZlE3uFJELVlNIOLJ1OE=";
+
+    fn request(timestamp: &str, signature: &str) -> actix_web::HttpRequest {
+        test::TestRequest::default()
+            .insert_header(("webhook-id", MSG_ID))
+            .insert_header(("webhook-timestamp", timestamp))
+            .insert_header(("webhook-signature", signature))
+            .to_http_request()
+    }
+
+    #[actix_web::test]
+    async fn test_specification_vector() {
+        // The vector is old, so widen the tolerance for this check
+        let config = create_signature_config(json!({
+            "scheme": "standard_webhooks",
+            "timestamp": { "tolerance_seconds": 10_000_000_000u64 },
+            "secret_env": "STANDARD_WEBHOOKS_SECRET"
+        }), SECRET);
+
+        assert!(verify_signature(&request(TIMESTAMP, SIGNATURE), PAYLOAD.as_bytes(), &config).is_ok());
+
+        // One valid signature among several space-separated ones is enough
+        let several = format!("v1,Zm9v {} v2,ignored", SIGNATURE);
+        assert!(verify_signature(&request(TIMESTAMP, &several), PAYLOAD.as_bytes(), &config).is_ok());
+
+        assert!(verify_signature(&request(TIMESTAMP, "v1,Zm9v"), PAYLOAD.as_bytes(), &config).is_err());
+        assert!(verify_signature(&request("1614265331", SIGNATURE), PAYLOAD.as_bytes(), &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_default_tolerance() {
+        let config = create_signature_config(json!({
+            "scheme": "standard_webhooks",
+            "secret_env": "STANDARD_WEBHOOKS_SECRET"
+        }), SECRET);
+
+        assert_eq!(config.get_header(), "webhook-signature");
+        assert_eq!(config.get_dedup_header(), Some("webhook-id"));
+        assert_eq!(config.get_replay_window_seconds(), Some(300));
+
+        // The specification vector is far outside the default window
+        assert!(verify_signature(&request(TIMESTAMP, SIGNATURE), PAYLOAD.as_bytes(), &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_secret() {
+        let mut config: SignatureConfig = serde_json::from_value(json!({
+            "scheme": "standard_webhooks",
+            "secret_env": "STANDARD_WEBHOOKS_SECRET"
+        })).unwrap();
+        assert!(config.set_secret("whsec_not base64!").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_replay_with_extra_signature_rejected() {
+        let config = create_signature_config(json!({
+            "scheme": "standard_webhooks",
+            "secret_env": "STANDARD_WEBHOOKS_SECRET"
+        }), SECRET);
+        let guard = ReplayGuard::new(vec![("svix".to_string(), Duration::from_secs(300))]);
+
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let message = format!("{}.{}.{}", MSG_ID, timestamp, PAYLOAD);
+        let signature = format!("v1,{}", BASE64.encode(hmac_digest(SignatureAlgorithm::HmacSha256, config.get_secret(), message.as_bytes())));
+
+        let req = request(&timestamp, &signature);
+        assert!(verify_signed_request(&req, PAYLOAD.as_bytes(), "svix", &config, &guard).await.is_ok());
+
+        // Appending another entry leaves the matching signature, and the webhook-id, unchanged
+        let req = request(&timestamp, &format!("{} v1,AAAA", signature));
+        assert!(verify_signed_request(&req, PAYLOAD.as_bytes(), "svix", &config, &guard).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_retries_collapse_on_webhook_id() {
+        let cache = web::Data::new(OrderedCache::new(vec!["svix".to_string()]));
+
+        let req = test::TestRequest::post().to_http_request();
+
+        // A retry re-signs with a new timestamp, but keeps the same webhook-id
+        for body in [r#"{"n":1,"ts":1}"#, r#"{"n":1,"ts":2}"#] {
+            let (_, key) = data_receiver(&req, Bytes::from(body), "svix".to_string(), Some(MSG_ID.to_string()), cache.clone())
+                .await
+                .unwrap();
+            assert_eq!(key, MSG_ID);
+        }
+
+        let items = cache.remove_oldest("svix", 10).await.unwrap();
+        assert_eq!(items.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod test_public_key_signature {
+    use super::create_public_key_config;
+    use super::signature::verify_signature;
+    use super::signature_config::SignatureConfig;
+
+    use actix_web::test;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use ed25519_dalek::Signer;
+    use p256::pkcs8::EncodePublicKey;
+    use serde_json::json;
+
+    fn discord_config(signing_key: &ed25519_dalek::SigningKey) -> SignatureConfig {
+        create_public_key_config(json!({
+            "scheme": "ed25519",
+            "header": "X-Signature-Ed25519",
+            "public_key": hex::encode(signing_key.verifying_key().as_bytes()),
+            "timestamp": { "header": "X-Signature-Timestamp" },
+            "template": "{timestamp}{body}"
+        }))
+    }
+
+    #[actix_web::test]
+    async fn test_discord_style_ed25519_signature() {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let config = discord_config(&signing_key);
+        let body = br#"{"type":1}"#;
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+
+        let mut message = timestamp.clone().into_bytes();
+        message.extend_from_slice(body);
+        let signature = hex::encode(signing_key.sign(&message).to_bytes());
+
+        let req = test::TestRequest::post()
+            .insert_header(("X-Signature-Ed25519", signature.as_str()))
+            .insert_header(("X-Signature-Timestamp", timestamp.as_str()))
+            .to_http_request();
+        assert!(verify_signature(&req, body, &config).is_ok());
+        assert!(verify_signature(&req, br#"{"type":2}"#, &config).is_err());
+
+        // Signed by a different key
+        let other_config = discord_config(&ed25519_dalek::SigningKey::from_bytes(&[8; 32]));
+        assert!(verify_signature(&req, body, &other_config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_ecdsa_p256_signature() {
+        use p256::ecdsa::signature::Signer;
+
+        let signing_key = p256::ecdsa::SigningKey::from_slice(&[9; 32]).unwrap();
+        let public_key_der = signing_key.verifying_key().to_public_key_der().unwrap();
+        let body = br#"[{"event":"delivered"}]"#;
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+
+        let mut message = timestamp.clone().into_bytes();
+        message.extend_from_slice(body);
+        let signature: p256::ecdsa::Signature = signing_key.sign(&message);
+
+        // SendGrid style: base64 DER public key and base64 DER signature
+        let config = create_public_key_config(json!({
+            "scheme": "ecdsa-p256",
+            "header": "X-Twilio-Email-Event-Webhook-Signature",
+            "encoding": "base64",
+            "public_key": BASE64.encode(public_key_der.as_bytes()),
+            "timestamp": { "header": "X-Twilio-Email-Event-Webhook-Timestamp" },
+            "template": "{timestamp}{body}"
+        }));
+        let request = |signature: String| test::TestRequest::post()
+            .insert_header(("X-Twilio-Email-Event-Webhook-Signature", signature))
+            .insert_header(("X-Twilio-Email-Event-Webhook-Timestamp", timestamp.as_str()))
+            .to_http_request();
+
+        let req = request(BASE64.encode(signature.to_der().as_bytes()));
+        assert!(verify_signature(&req, body, &config).is_ok());
+        assert!(verify_signature(&req, b"[]", &config).is_err());
+
+        // Fixed-size r || s signatures are accepted too
+        let req = request(BASE64.encode(signature.to_bytes()));
+        assert!(verify_signature(&req, body, &config).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_public_key() {
+        for (scheme, public_key) in [("ed25519", "abcd"), ("ecdsa-p256", "not a key"), ("ed25519", "")] {
+            let mut config: SignatureConfig = serde_json::from_value(json!({
+                "scheme": scheme, "header": "X-Signature", "public_key": public_key
+            })).unwrap();
+            assert!(config.load_secret().is_err(), "{} {}", scheme, public_key);
+        }
+
+        let mut config: SignatureConfig = serde_json::from_value(json!({
+            "scheme": "ed25519", "header": "X-Signature"
+        })).unwrap();
+        assert!(config.load_secret().is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_in_band_challenge {
+    use super::data_config::InBandChallengeConfig;
+    use super::in_band_challenge::answer_in_band_challenge;
+
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    fn slack_config() -> InBandChallengeConfig {
+        serde_json::from_value(json!({
+            "when": { "in": "body", "locate": "type", "equals": "url_verification" },
+            "value": { "in": "body", "locate": "challenge" },
+            "response": { "type": "application/json", "data": "@challenge", "in_path": "challenge" }
+        })).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_matching_challenge_is_answered() {
+        let req = test::TestRequest::post().uri("/callhook/slack").to_http_request();
+        let body = Bytes::from(json!({"type": "url_verification", "challenge": "3eZbrw1aB"}).to_string());
+
+        let response = answer_in_band_challenge(&req, &body, &slack_config()).unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response_body = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(response_body, Bytes::from(r#"{"challenge":"3eZbrw1aB"}"#));
+    }
+
+    #[actix_web::test]
+    async fn test_regular_events_pass_through() {
+        let req = test::TestRequest::post().uri("/callhook/slack").to_http_request();
+
+        // Predicate does not match
+        let body = Bytes::from(json!({"type": "event_callback", "event": {}}).to_string());
+        assert!(answer_in_band_challenge(&req, &body, &slack_config()).unwrap().is_none());
+
+        // Predicate field is missing
+        let body = Bytes::from(json!({"event": {}}).to_string());
+        assert!(answer_in_band_challenge(&req, &body, &slack_config()).unwrap().is_none());
+
+        // Predicate field does not match its pattern
+        let config: InBandChallengeConfig = serde_json::from_value(json!({
+            "when": { "in": "body", "locate": "type", "pattern": "^url_(verification)$", "equals": "verification" },
+            "value": { "in": "body", "locate": "challenge" },
+            "response": { "type": "text/plain", "data": "@challenge" }
+        })).unwrap();
+        let body = Bytes::from(json!({"type": "url_verification_v2", "challenge": "x"}).to_string());
+        assert!(answer_in_band_challenge(&req, &body, &config).unwrap().is_none());
+        let body = Bytes::from(json!({"type": "url_verification", "challenge": "x"}).to_string());
+        assert!(answer_in_band_challenge(&req, &body, &config).unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_presence_only_condition() {
+        let config: InBandChallengeConfig = serde_json::from_value(json!({
+            "when": { "in": "body", "locate": "challenge" },
+            "value": { "in": "body", "locate": "challenge" },
+            "response": { "type": "text/plain", "data": "@challenge" }
+        })).unwrap();
+        let req = test::TestRequest::post().uri("/callhook/hook").to_http_request();
+
+        let body = Bytes::from(json!({"challenge": "abc"}).to_string());
+        let response = answer_in_band_challenge(&req, &body, &config).unwrap().unwrap();
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), Bytes::from("abc"));
+
+        let body = Bytes::from(json!({"event": "abc"}).to_string());
+        assert!(answer_in_band_challenge(&req, &body, &config).unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_ping_is_answered_with_fixed_reply() {
+        // Discord interactions expect {"type":1} back for a PING
+        let config: InBandChallengeConfig = serde_json::from_value(json!({
+            "when": { "in": "body", "locate": "type", "equals": "1" },
+            "response": { "type": "application/json", "data": { "type": 1 } }
+        })).unwrap();
+        let req = test::TestRequest::post().uri("/callhook/discord").to_http_request();
+
+        let body = Bytes::from(json!({"type": 1, "id": "ping"}).to_string());
+        let response = answer_in_band_challenge(&req, &body, &config).unwrap().unwrap();
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), Bytes::from(r#"{"type":1}"#));
+
+        let body = Bytes::from(json!({"type": 2, "data": {}}).to_string());
+        assert!(answer_in_band_challenge(&req, &body, &config).unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_matching_condition_without_challenge_value() {
+        let req = test::TestRequest::post().uri("/callhook/slack").to_http_request();
+        let body = Bytes::from(json!({"type": "url_verification"}).to_string());
+        assert!(answer_in_band_challenge(&req, &body, &slack_config()).is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_twilio_signature {
+    use super::create_signature_config;
+    use super::data_receiver::data_receiver;
+    use super::signature::*;
+    use super::signature_config::SignatureConfig;
+    use crate::cache::OrderedCache;
+
+    use actix_web::{test, web};
+    use actix_web::http::header;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    // Example from Twilio's webhook security documentation
+    const AUTH_TOKEN: &str = "12345";
+    const URI: &str = "/myapp.php?foo=1&bar=2";
+    const BODY: &str = "CallSid=CA1234567890ABCDE&Caller=%2B12349013030&Digits=1234&From=%2B12349013030&To=%2B18005551212";
+    const SIGNATURE: &str = "0/KCTR6DLpKmkAf8muzZqo1nDgQ=";
+
+    fn twilio_config(public_base_url: Option<&str>) -> SignatureConfig {
+        create_signature_config(json!({
+            "scheme": "twilio",
+            "public_base_url": public_base_url,
+            "secret_env": "TWILIO_AUTH_TOKEN"
+        }), AUTH_TOKEN)
+    }
+
+    fn request(signature: &str) -> actix_web::HttpRequest {
+        test::TestRequest::post()
+            .uri(URI)
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .insert_header(("X-Twilio-Signature", signature))
+            .to_http_request()
+    }
+
+    #[actix_web::test]
+    async fn test_documented_example() {
+        let config = twilio_config(Some("https://mycompany.com/"));
+        assert!(verify_signature(&request(SIGNATURE), BODY.as_bytes(), &config).is_ok());
+
+        // Parameter order in the body does not matter
+        let reordered = "To=%2B18005551212&From=%2B12349013030&Digits=1234&Caller=%2B12349013030&CallSid=CA1234567890ABCDE";
+        assert!(verify_signature(&request(SIGNATURE), reordered.as_bytes(), &config).is_ok());
+
+        // Tampered parameter
+        let tampered = BODY.replace("Digits=1234", "Digits=4321");
+        assert!(verify_signature(&request(SIGNATURE), tampered.as_bytes(), &config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_url_is_part_of_signature() {
+        // Without the public base URL the local scheme and host are signed, which differ here
+        let config = twilio_config(None);
+        assert!(verify_signature(&request(SIGNATURE), BODY.as_bytes(), &config).is_err());
+
+        let config = twilio_config(Some("https://other.example.com"));
+        assert!(verify_signature(&request(SIGNATURE), BODY.as_bytes(), &config).is_err());
+
+        // Missing header
+        let req = test::TestRequest::post().uri(URI).to_http_request();
+        assert!(verify_signature(&req, BODY.as_bytes(), &twilio_config(Some("https://mycompany.com"))).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_form_body_is_cached_as_json() {
+        let cache = web::Data::new(OrderedCache::new(vec!["twilio".to_string()]));
+
+        data_receiver(&request(SIGNATURE), Bytes::from(BODY), "twilio".to_string(), None, cache.clone())
+            .await
+            .unwrap();
+
+        let items = cache.remove_oldest("twilio", 10).await.unwrap();
+        assert_eq!(items[0].1["_pollhook"], json!({ "content_type": "application/x-www-form-urlencoded" }));
+        assert_eq!(items[0].1["body"], json!({
+            "CallSid": "CA1234567890ABCDE",
+            "Caller": "+12349013030",
+            "Digits": "1234",
+            "From": "+12349013030",
+            "To": "+18005551212"
+        }));
+    }
+}
+
+#[cfg(test)]
+mod test_form_payloads {
+    use super::data_receiver::data_receiver;
+    use crate::cache::OrderedCache;
+
+    use actix_web::{test, web};
+    use actix_web::http::header;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_multipart_body_is_cached_as_json() {
+        let cache = web::Data::new(OrderedCache::new(vec!["mailgun".to_string()]));
+        let req = test::TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "multipart/form-data; boundary=\"xyz\""))
+            .to_http_request();
+        let body = Bytes::from(
+            "--xyz\r\nContent-Disposition: form-data; name=\"sender\"\r\n\r\nbob@example.com\r\n\
+             --xyz\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\r\n\x00\x01\r\n\
+             --xyz--\r\n"
+        );
+
+        data_receiver(&req, body, "mailgun".to_string(), None, cache.clone()).await.unwrap();
+
+        let items = cache.remove_oldest("mailgun", 10).await.unwrap();
+        assert_eq!(items[0].1["_pollhook"]["content_type"], "multipart/form-data; boundary=\"xyz\"");
+        assert_eq!(items[0].1["body"], json!({
+            "sender": "bob@example.com",
+            "file": { "filename": "a.bin", "content_type": "application/octet-stream", "data": "AAE=" }
+        }));
+    }
+
+    #[actix_web::test]
+    async fn test_repeated_form_keys_become_arrays() {
+        let cache = web::Data::new(OrderedCache::new(vec!["form".to_string()]));
+        let req = test::TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .to_http_request();
+
+        data_receiver(&req, Bytes::from("tag=a&tag=b&tag=c&id=1"), "form".to_string(), None, cache.clone()).await.unwrap();
+
+        let items = cache.remove_oldest("form", 10).await.unwrap();
+        assert_eq!(items[0].1, json!({
+            "_pollhook": { "content_type": "application/x-www-form-urlencoded" },
+            "body": { "tag": ["a", "b", "c"], "id": "1" }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod test_xml_payloads {
+    use super::data_receiver::data_receiver;
+    use crate::cache::OrderedCache;
+
+    use actix_web::{test, web};
+    use actix_web::http::header;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_xml_body_is_cached_as_json() {
+        let cache = web::Data::new(OrderedCache::new(vec!["wechat".to_string()]));
+        let req = test::TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/xml"))
+            .to_http_request();
+        let body = Bytes::from(
+            "<xml><ToUserName><![CDATA[gh_123]]></ToUserName><MsgId>42</MsgId></xml>"
+        );
+
+        data_receiver(&req, body, "wechat".to_string(), None, cache.clone()).await.unwrap();
+
+        let items = cache.remove_oldest("wechat", 10).await.unwrap();
+        assert_eq!(items[0].1, json!({
+            "_pollhook": { "content_type": "application/xml" },
+            "body": { "xml": { "ToUserName": "gh_123", "MsgId": "42" } }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod test_path_params {
+    use super::data_app;
+    use super::data_receiver::data_receiver;
+    use crate::cache::OrderedCache;
+
+    use actix_web::{test, web};
+    use bytes::Bytes;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_params_are_recorded_in_envelope() {
+        let cache = web::Data::new(OrderedCache::new(vec!["tenant_events".to_string()]));
+        let req = test::TestRequest::post()
+            .uri("/callhook/acme/events")
+            .param("tenant", "acme")
+            .to_http_request();
+
+        data_receiver(&req, Bytes::from(r#"{"id":1}"#), "tenant_events".to_string(), None, cache.clone()).await.unwrap();
+
+        let items = cache.remove_oldest("tenant_events", 10).await.unwrap();
+        assert_eq!(items[0].1, json!({
+            "_pollhook": { "params": { "tenant": "acme" } },
+            "body": { "id": 1 }
+        }));
+    }
+
+    #[actix_web::test]
+    async fn test_routes_without_params_keep_bare_body() {
+        let cache = web::Data::new(OrderedCache::new(vec!["plain".to_string()]));
+        let req = test::TestRequest::post().uri("/callhook/plain").to_http_request();
+
+        data_receiver(&req, Bytes::from(r#"{"id":1}"#), "plain".to_string(), None, cache.clone()).await.unwrap();
+
+        let items = cache.remove_oldest("plain", 10).await.unwrap();
+        assert_eq!(items[0].1, json!({ "id": 1 }));
+    }
+
+    #[actix_web::test]
+    async fn test_data_route_captures_params() {
+        let cache = web::Data::new(OrderedCache::new(vec!["tenant_events".to_string()]));
+        let app = test::init_service(data_app(r#"
+verification: {}
+data:
+  tenant_events:
+    path: /callhook/{tenant}/*/events
+    method: POST
+"#, "tenant_events", cache.clone())).await;
+
+        let req = test::TestRequest::post().uri("/callhook/globex/eu/events").set_payload("{}").to_request();
+        test::call_service(&app, req).await;
+
+        let items = cache.remove_oldest("tenant_events", 10).await.unwrap();
+        assert_eq!(items[0].1["_pollhook"]["params"], json!({ "tenant": "globex" }));
+    }
+}
+
+#[cfg(test)]
+mod test_source_ip_allowlist {
+    use super::data_app;
+    use crate::cache::OrderedCache;
+
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web};
+
+    #[actix_web::test]
+    async fn test_only_allowed_sources_are_cached() {
+        let cache = web::Data::new(OrderedCache::new(vec!["github".to_string()]));
+        let app = test::init_service(data_app(r#"
+trusted_proxies:
+  ranges: [10.0.0.0/8]
+verification: {}
+data:
+  github:
+    path: /callhook/github
+    method: POST
+    allowed_ips:
+      ranges: [192.30.252.0/22]
+"#, "github", cache.clone())).await;
+
+        let send = |peer: &str, forwarded_for: &str| test::TestRequest::post()
+            .uri("/callhook/github")
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for.to_string()))
+            .set_payload(r#"{"id":1}"#)
+            .to_request();
+
+        // Forwarded by the trusted proxy from GitHub's range
+        let response = test::call_service(&app, send("10.0.0.2:40000", "192.30.252.41")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A client outside the range cannot claim to be inside it
+        let response = test::call_service(&app, send("198.51.100.7:40000", "192.30.252.41")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let items = cache.remove_oldest("github", 10).await.unwrap();
+        assert_eq!(items.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod test_raw_payloads {
+    use super::data_app;
+    use super::data_receiver::data_receiver;
+    use crate::cache::OrderedCache;
+
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web};
+    use bytes::Bytes;
+    use serde_json::json;
+
+    async fn store(content_type: Option<&str>, body: &'static [u8]) -> serde_json::Value {
+        let cache = web::Data::new(OrderedCache::new(vec!["raw".to_string()]));
+        let mut req = test::TestRequest::post().uri("/callhook/raw");
+        if let Some(content_type) = content_type {
+            req = req.insert_header((header::CONTENT_TYPE, content_type));
+        }
+
+        data_receiver(&req.to_http_request(), Bytes::from_static(body), "raw".to_string(), None, cache.clone()).await.unwrap();
+        cache.remove_oldest("raw", 10).await.unwrap().remove(0).1
+    }
+
+    #[actix_web::test]
+    async fn test_text_is_stored_as_utf8() {
+        assert_eq!(store(Some("text/plain; charset=utf-8"), b"disk usage at 91%").await, json!({
+            "_pollhook": { "content_type": "text/plain; charset=utf-8", "encoding": "utf8" },
+            "body": "disk usage at 91%"
+        }));
+
+        // A document that does not parse is kept as received
+        assert_eq!(store(Some("application/xml"), b"<xml><unclosed>").await, json!({
+            "_pollhook": { "content_type": "application/xml", "encoding": "utf8" },
+            "body": "<xml><unclosed>"
+        }));
+    }
+
+    #[actix_web::test]
+    async fn test_binary_is_stored_as_base64() {
+        assert_eq!(store(Some("image/png"), b"\x89PNG\r\n\x1a\n").await, json!({
+            "_pollhook": { "content_type": "image/png", "encoding": "base64" },
+            "body": "iVBORw0KGgo="
+        }));
+
+        assert_eq!(store(None, b"\xff\x00").await["_pollhook"]["content_type"], "application/octet-stream");
+    }
+
+    #[actix_web::test]
+    async fn test_json_is_kept_bare() {
+        // Some providers, such as SNS, label JSON as text/plain
+        assert_eq!(store(Some("text/plain"), br#"{"Type":"Notification"}"#).await, json!({ "Type": "Notification" }));
+        assert_eq!(store(None, br#"[1,2]"#).await, json!([1, 2]));
+    }
+
+    #[actix_web::test]
+    async fn test_failed_delivery_is_not_acknowledged() {
+        // The cache was built without the alias, so the event cannot be stored
+        let cache = web::Data::new(OrderedCache::new(Vec::new()));
+        let app = test::init_service(data_app(r#"
+verification: {}
+data:
+  events:
+    path: /callhook/events
+    method: POST
+"#, "events", cache)).await;
+
+        let req = test::TestRequest::post().uri("/callhook/events").set_payload("not json").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
+
+#[cfg(test)]
+mod test_request_envelope {
+    use super::data_app;
+    use crate::cache::OrderedCache;
+
+    use actix_web::{test, web};
+    use serde_json::json;
+
+    const CONFIG: &str = r#"
+trusted_proxies:
+  ranges: [10.0.0.0/8]
+verification: {}
+data:
+  github:
+    path: /callhook/github
+    method: POST
+    envelope:
+      include_headers: [X-GitHub-Event, X-GitHub-Delivery, X-Hub-Signature-256]
+      raw_body: true
+  audit:
+    path: /callhook/audit
+    method: POST
+    envelope:
+      exclude_headers: [Authorization, X-Forwarded-For]
+"#;
+
+    /// Send one request to an alias and return the event it cached
+    async fn deliver(alias: &str, req: test::TestRequest) -> serde_json::Value {
+        let cache = web::Data::new(OrderedCache::new(vec![alias.to_string()]));
+        let app = test::init_service(data_app(CONFIG, alias, cache.clone())).await;
+
+        test::call_service(&app, req.to_request()).await;
+        cache.remove_oldest(alias, 10).await.unwrap().remove(0).1
+    }
+
+    #[actix_web::test]
+    async fn test_allowlisted_headers_and_raw_body_are_recorded() {
+        let event = deliver("github", test::TestRequest::post()
+            .uri("/callhook/github?source=app&tag=a&tag=b")
+            .peer_addr("10.0.0.2:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "192.30.252.41"))
+            .insert_header(("X-GitHub-Event", "push"))
+            .insert_header(("X-GitHub-Delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958"))
+            .insert_header(("X-Hub-Signature-256", "sha256=abc"))
+            .insert_header(("User-Agent", "GitHub-Hookshot/044aadd"))
+            .set_payload(r#"{"ref": "refs/heads/main"}"#)
+        ).await;
+
+        let metadata = &event["_pollhook"];
+        assert_eq!(metadata["method"], "POST");
+        assert_eq!(metadata["path"], "/callhook/github");
+        assert_eq!(metadata["query"], json!({ "source": "app", "tag": ["a", "b"] }));
+        assert_eq!(metadata["source_ip"], "192.30.252.41");
+        assert_eq!(metadata["headers"], json!({
+            "x-github-event": "push",
+            "x-github-delivery": "72d3162e-cc78-11e3-81ab-4c9367dc0958",
+            "x-hub-signature-256": "sha256=abc"
+        }));
+        // The exact bytes, with the whitespace the parsed body loses
+        assert_eq!(metadata["raw_body"], json!({ "encoding": "utf8", "data": r#"{"ref": "refs/heads/main"}"# }));
+        assert!(chrono::DateTime::parse_from_rfc3339(metadata["received_at"].as_str().unwrap()).is_ok());
+        assert_eq!(event["body"], json!({ "ref": "refs/heads/main" }));
+    }
+
+    #[actix_web::test]
+    async fn test_denylisted_headers_are_dropped() {
+        let event = deliver("audit", test::TestRequest::post()
+            .uri("/callhook/audit")
+            .peer_addr("203.0.113.9:40000".parse().unwrap())
+            .insert_header(("Authorization", "Bearer secret"))
+            .insert_header(("X-Request-Id", "req-1"))
+            .set_payload(r#"{"id":1}"#)
+        ).await;
+
+        let metadata = &event["_pollhook"];
+        assert_eq!(metadata["source_ip"], "203.0.113.9");
+        assert_eq!(metadata["query"], json!({}));
+        assert_eq!(metadata["headers"]["x-request-id"], "req-1");
+        assert!(metadata["headers"].get("authorization").is_none());
+        assert!(metadata.get("raw_body").is_none());
+    }
+}
+
+#[cfg(test)]
+mod test_dedup_key {
+    use super::data_app;
+    use super::signature::hmac_digest;
+    use super::signature_config::SignatureAlgorithm;
+    use crate::cache::OrderedCache;
+    use crate::polling_handler::data_polling::retrieve_data_with_polling;
+    use crate::polling_handler::polling_config::PollingConfig;
+
+    use actix_web::{test, web};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+
+    const CONFIG: &str = r#"
+verification: {}
+data:
+  github:
+    path: /callhook/github
+    method: POST
+    dedup_key:
+      - in: header
+        locate: X-GitHub-Delivery
+  acme:
+    path: /callhook/acme
+    method: POST
+    dedup_key:
+      - in: header
+        locate: X-Tenant
+      - in: body
+        locate: /id
+  svix:
+    path: /callhook/svix
+    method: POST
+    signature:
+      scheme: standard_webhooks
+      secret_env: SVIX_WEBHOOK_SECRET
+    dedup_key:
+      - in: body
+        locate: /id
+"#;
+
+    /// Send each request to an alias and poll back the IDs of what was cached
+    async fn deliver(alias: &str, requests: Vec<test::TestRequest>) -> Vec<String> {
+        let cache = web::Data::new(OrderedCache::new(vec![alias.to_string()]));
+        let app = test::init_service(data_app(CONFIG, alias, cache.clone())).await;
+
+        for req in requests {
+            test::call_service(&app, req.to_request()).await;
+        }
+        let response = retrieve_data_with_polling(alias, &cache, PollingConfig::new()).await.unwrap();
+        assert_eq!(response.ids.len(), response.data.len());
+        response.ids
+    }
+
+    #[actix_web::test]
+    async fn test_header_key_collapses_redeliveries() {
+        let delivery = |body: &str| test::TestRequest::post()
+            .uri("/callhook/github")
+            .insert_header(("X-GitHub-Delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958"))
+            .set_payload(body.to_string());
+
+        // A redelivery with a different body is still the same event
+        let ids = deliver("github", vec![delivery(r#"{"attempt": 1}"#), delivery(r#"{"attempt": 2}"#)]).await;
+        assert_eq!(ids, vec!["72d3162e-cc78-11e3-81ab-4c9367dc0958"]);
+    }
+
+    #[actix_web::test]
+    async fn test_composite_key_and_hash_fallback() {
+        let ids = deliver("acme", vec![
+            test::TestRequest::post()
+                .uri("/callhook/acme")
+                .insert_header(("X-Tenant", "acme"))
+                .set_payload(r#"{"id": "evt_1"}"#),
+            // Without the header the body hash is used
+            test::TestRequest::post()
+                .uri("/callhook/acme")
+                .set_payload(r#"{"id": "evt_2"}"#),
+        ]).await;
+
+        assert_eq!(ids[0], "acme:evt_1");
+        assert_eq!(ids[1].len(), 64);
+        assert!(ids[1].chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[actix_web::test]
+    async fn test_composite_key_parts_are_escaped() {
+        let event = |tenant: &str, id: &str| test::TestRequest::post()
+            .uri("/callhook/acme")
+            .insert_header(("X-Tenant", tenant.to_string()))
+            .set_payload(format!(r#"{{"id": "{}"}}"#, id));
+
+        // Parts that would join to the same text stay distinct events
+        let ids = deliver("acme", vec![event("a:b", "c"), event("a", "b:c"), event("a\\", ":c")]).await;
+        assert_eq!(ids, vec![r"a\:b:c", r"a:b\:c", r"a\\:\:c"]);
+    }
+
+    #[actix_web::test]
+    async fn test_missing_key_falls_back_to_webhook_id() {
+        // The config's secret is not loaded here, so deliveries are signed with an empty key
+        let delivery = |body: &str| {
+            let timestamp = chrono::Utc::now().timestamp().to_string();
+            let message = format!("msg_1.{}.{}", timestamp, body);
+            let signature = BASE64.encode(hmac_digest(SignatureAlgorithm::HmacSha256, b"", message.as_bytes()));
+            test::TestRequest::post()
+                .uri("/callhook/svix")
+                .insert_header(("webhook-id", "msg_1"))
+                .insert_header(("webhook-timestamp", timestamp))
+                .insert_header(("webhook-signature", format!("v1,{}", signature)))
+                .set_payload(body.to_string())
+        };
+
+        // Neither body has the configured /id, and the redelivery's body differs
+        let ids = deliver("svix", vec![delivery(r#"{"attempt": 1}"#), delivery(r#"{"attempt": 2}"#)]).await;
+        assert_eq!(ids, vec!["msg_1"]);
+    }
+}
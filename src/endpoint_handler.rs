@@ -1,43 +1,200 @@
-use actix_web::{web, HttpRequest, Responder, HttpResponse, http::header};
+use actix_web::{web, HttpMessage, HttpRequest, Responder, HttpResponse, http::header};
+use crate::verification_handler::extractors::PathParams;
 use crate::verification_handler::verification::verification_handler;
 use crate::polling_handler::data_polling::{retrieve_data_with_polling, DataResponse};
 use crate::webhook_config::WebhookConfig;
 use crate::cache::{OrderedCache};
-use crate::data_handler::data_receiver::data_receiver;
-use log::{info, error};
+use crate::data_handler::data_receiver::{collect_payload, data_receiver, extract_dedup_key};
+use crate::data_handler::envelope::RequestMetadata;
+use crate::data_handler::in_band_challenge::answer_in_band_challenge;
+use crate::data_handler::replay_guard::ReplayGuard;
+use crate::data_handler::signature::verify_signed_request;
+use crate::client_cert::PeerCertificate;
+use crate::jwt_auth::JwtClaims;
+use crate::lockout::{AuthFailure, FailureTracker};
+use crate::token_set::TokenSet;
+use std::net::IpAddr;
+use log::{info, error, warn};
 use serde_json::json;
 
+/// 429 for a source that is banned after repeated authentication failures
+async fn banned_response(lockout: &FailureTracker, source_ip: Option<IpAddr>) -> Option<HttpResponse> {
+    let banned_until = lockout.banned_until(source_ip?).await?;
+    let retry_after = (banned_until - chrono::Utc::now()).num_seconds().max(1);
+
+    Some(HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(json!({
+            "error": "Too Many Requests",
+            "message": "Too many failed authentication attempts"
+        })))
+}
+
+/// Record a failed attempt, then answer with `response` once its delay has passed
+async fn reject(
+    lockout: &FailureTracker,
+    source_ip: Option<IpAddr>,
+    req: &HttpRequest,
+    reason: &str,
+    response: HttpResponse,
+) -> HttpResponse {
+    let delay = lockout.record_failure(source_ip, req.path(), reason).await;
+    actix_web::rt::time::sleep(delay).await;
+    response
+}
+
 pub async fn verification_endpoint_handler(
     req: HttpRequest,
     payload: web::Payload,
+    name: String,
     config: web::Data<WebhookConfig>,
+    lockout: web::Data<FailureTracker>,
 ) -> impl Responder {
-    let verification_config = config.get_verification_config_owned();
+    let Some(verification_config) = config.get_verification_config_owned(&name) else {
+        error!("Verification block not found: {}", name);
+        return HttpResponse::NotFound().finish();
+    };
+
+    let source_ip = config.get_trusted_proxies().client_ip(&req);
+    if let Some(response) = banned_response(&lockout, source_ip).await {
+        return response;
+    }
+
+    if let Some(allowed_ips) = verification_config.get_allowed_ips()
+        && let Err(e) = allowed_ips.check(source_ip, &format!("verification block {}", name)).await
+    {
+        return HttpResponse::from_error(e);
+    }
 
-    match verification_handler(req, payload, verification_config).await {
+    let response = match verification_handler(req.clone(), payload, verification_config).await {
         Ok(response) => response,
         Err(e) => {
             error!("Verification failed: {}", e);
             HttpResponse::BadRequest().body(format!("Verification failed: {}", e))
         }
+    };
+
+    // The token checks leave the reason a request was rejected in its extensions
+    let failure = req.extensions().get::<AuthFailure>().copied();
+    match failure {
+        Some(AuthFailure(reason)) => reject(&lockout, source_ip, &req, reason, response).await,
+        None => response,
     }
 }
 
 pub async fn data_endpoint_handler(
-    _req: HttpRequest,
+    req: HttpRequest,
     payload: web::Payload,
     alias: String,
-    _config: web::Data<WebhookConfig>,
+    config: web::Data<WebhookConfig>,
     cache: web::Data<OrderedCache>,
+    replay_guard: web::Data<ReplayGuard>,
 ) -> impl Responder {
-    match data_receiver(payload, alias.clone(), cache).await {
+    let received_at = chrono::Utc::now();
+    let endpoint_config = config.get_data_config().get(&alias);
+    let source_ip = config.get_trusted_proxies().client_ip(&req);
+
+    // Requests from outside the allowlist are rejected before their payload is read
+    if let Some(allowed_ips) = endpoint_config.and_then(|e| e.get_allowed_ips())
+        && let Err(e) = allowed_ips.check(source_ip, &format!("alias {}", alias)).await
+    {
+        return HttpResponse::from_error(e);
+    }
+
+    // Client certificates are checked next, so a sender without an allowed one is rejected before its payload is read
+    if let Some(client_cert_config) = endpoint_config.and_then(|e| e.get_client_cert_config()) {
+        let peer = req.conn_data::<PeerCertificate>().cloned();
+        if let Err(e) = client_cert_config.check(peer.as_ref(), &alias) {
+            return HttpResponse::from_error(e);
+        }
+        if let Some(peer) = peer {
+            req.extensions_mut().insert(peer);
+        }
+    }
+
+    let body = match collect_payload(payload).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to read payload for alias {}: {}", alias, e);
+            return HttpResponse::from_error(e);
+        }
+    };
+
+    if let Some(envelope_config) = endpoint_config.and_then(|e| e.get_envelope_config()) {
+        let metadata = RequestMetadata::new(&req, envelope_config, received_at, source_ip, &body);
+        req.extensions_mut().insert(metadata);
+    }
+
+    // Record the named `{param}` segments the alias's path pattern captured
+    if let Some(params) = endpoint_config.and_then(|e| e.get_path_pattern().matches(req.path())) {
+        req.extensions_mut().insert(PathParams(params));
+    }
+
+    let signature_config = endpoint_config.and_then(|e| e.get_signature_config());
+
+    // Reject payloads that fail signature verification before they reach the cache
+    if let Some(signature_config) = signature_config
+        && let Err(e) = verify_signed_request(&req, &body, &alias, signature_config, &replay_guard).await
+    {
+        error!("Signature verification failed for alias {}: {}", alias, e);
+        return HttpResponse::from_error(e);
+    }
+
+    // Bearer tokens are checked before anything is answered or cached
+    if let Some(jwt_config) = endpoint_config.and_then(|e| e.get_jwt_config()) {
+        match jwt_config.verify_request(&req).await {
+            Ok(claims) => {
+                req.extensions_mut().insert(JwtClaims(claims));
+            }
+            Err(e) => {
+                error!("JWT verification failed for alias {}", alias);
+                return HttpResponse::from_error(e);
+            }
+        }
+    }
+
+    // In-band verification challenges are answered directly and never cached
+    if let Some(challenge_config) = endpoint_config.and_then(|e| e.get_challenge_config()) {
+        match answer_in_band_challenge(&req, &body, challenge_config) {
+            Ok(Some(response)) => return response,
+            Ok(None) => {}
+            Err(e) => {
+                error!("In-band challenge failed for alias {}: {}", alias, e);
+                return HttpResponse::from_error(e);
+            }
+        }
+    }
+
+    // The alias's own dedup key comes first, then the delivery ID its signature scheme defines
+    let dedup_key = endpoint_config.map(|e| e.get_dedup_key()).unwrap_or_default();
+    let configured_key = if dedup_key.is_empty() {
+        None
+    } else {
+        match extract_dedup_key(&req, dedup_key, &body) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                warn!("Dedup key missing for alias {}, using the delivery ID or body hash: {}", alias, e);
+                None
+            }
+        }
+    };
+    let delivery_id = configured_key.or_else(|| {
+        signature_config
+            .and_then(|s| s.get_dedup_header())
+            .and_then(|h| req.headers().get(h))
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    });
+
+    match data_receiver(&req, body, alias.clone(), delivery_id, cache).await {
         Ok((alias, key)) => {
             info!("Successfully stored data for alias: {} with key: {}", alias, key);
             HttpResponse::Ok().finish()
         }
+        // Fail the delivery so the provider retries it, rather than report an event that was dropped
         Err(e) => {
             error!("Failed to process data for alias {}: {}", alias, e);
-            HttpResponse::Ok().finish() // Always return 200
+            HttpResponse::InternalServerError().finish()
         }
     }
 }
@@ -48,8 +205,14 @@ pub async fn data_retrieval_handler_with_auth(
     path: web::Path<String>,
     config: web::Data<WebhookConfig>,
     cache: web::Data<OrderedCache>,
-    token: web::Data<String>,
+    tokens: web::Data<TokenSet>,
+    lockout: web::Data<FailureTracker>,
 ) -> HttpResponse {
+    let source_ip = config.get_trusted_proxies().client_ip(&req);
+    if let Some(response) = banned_response(&lockout, source_ip).await {
+        return response;
+    }
+
     // Check Authorization header
     let auth_header = req.headers()
         .get(header::AUTHORIZATION)
@@ -58,7 +221,7 @@ pub async fn data_retrieval_handler_with_auth(
     match auth_header {
         Some(auth) if auth.starts_with("Bearer ") => {
             let provided_token = &auth[7..]; // Skip "Bearer " prefix
-            if provided_token == token.get_ref() {
+            if tokens.is_valid(provided_token) {
                 let alias = path.into_inner();
                 let polling_config = config.get_polling_config_owned();
 
@@ -70,21 +233,51 @@ pub async fn data_retrieval_handler_with_auth(
                             success: false,
                             message: format!("Failed to retrieve data: {}", e),
                             count: 0,
+                            ids: Vec::new(),
                             data: Vec::new(),
                         })
                     }
                 }
             } else {
-                HttpResponse::Unauthorized().json(json!({
+                reject(&lockout, source_ip, &req, "invalid polling token", HttpResponse::Unauthorized().json(json!({
                     "error": "Unauthorized",
                     "message": "Invalid token"
-                }))
+                }))).await
             }
         }
-        _ => HttpResponse::Unauthorized().json(json!({
+        _ => reject(&lockout, source_ip, &req, "missing polling token", HttpResponse::Unauthorized().json(json!({
             "error": "Unauthorized",
             "message": "Missing or invalid Authorization header. Use 'Bearer <token>' format."
-        })),
+        }))).await,
+    }
+}
+
+/// List the source addresses banned after repeated authentication failures
+pub async fn bans_handler(
+    req: HttpRequest,
+    config: web::Data<WebhookConfig>,
+    lockout: web::Data<FailureTracker>,
+    admin_tokens: TokenSet,
+) -> HttpResponse {
+    let source_ip = config.get_trusted_proxies().client_ip(&req);
+    if let Some(response) = banned_response(&lockout, source_ip).await {
+        return response;
+    }
+
+    let provided_token = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer "));
+
+    match provided_token {
+        Some(token) if admin_tokens.is_valid(token) => {
+            let bans = lockout.current_bans();
+            HttpResponse::Ok().json(json!({ "count": bans.len(), "bans": bans }))
+        }
+        _ => reject(&lockout, source_ip, &req, "invalid admin token", HttpResponse::Unauthorized().json(json!({
+            "error": "Unauthorized",
+            "message": "Invalid admin token"
+        }))).await,
     }
 }
 
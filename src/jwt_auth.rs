@@ -0,0 +1,297 @@
+// jwt_auth.rs
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use actix_web::{Error, HttpRequest};
+use jsonwebtoken::jwk::{Jwk, JwkSet, KeyAlgorithm};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::{error, info, warn};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+pub mod test;
+
+/// Algorithms accepted in a token header; shared-secret HMAC algorithms never are
+const ASYMMETRIC_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+    Algorithm::PS256, Algorithm::PS384, Algorithm::PS512,
+    Algorithm::ES256, Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// A JWKS URL is fetched again at most this often when a token names an unknown key
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Claims copied from a verified token, stored in the request extensions for the event envelope
+#[derive(Debug, Clone)]
+pub struct JwtClaims(pub BTreeMap<String, Value>);
+
+/// Keys loaded from the JWKS source, shared by every worker
+struct KeyCache {
+    keys: JwkSet,
+    fetched_at: Option<Instant>,
+}
+
+impl Default for KeyCache {
+    fn default() -> Self {
+        Self { keys: JwkSet { keys: Vec::new() }, fetched_at: None }
+    }
+}
+
+impl fmt::Debug for KeyCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyCache").field("keys", &self.keys.keys.len()).finish()
+    }
+}
+
+/// Signed bearer token check (OIDC style, e.g. Google Pub/Sub push)
+#[derive(Clone, Debug, Deserialize)]
+pub struct JwtConfig {
+    #[serde(default = "default_header")]
+    header: String,
+    #[serde(default)]
+    jwks_file: Option<String>,
+    #[serde(default)]
+    jwks_url: Option<String>,
+    #[serde(default = "default_refresh_seconds")]
+    refresh_seconds: u64, // How long keys fetched from jwks_url are used before fetching again
+    #[serde(deserialize_with = "one_or_many")]
+    audience: Vec<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    issuer: Vec<String>,
+    #[serde(default = "default_leeway_seconds")]
+    leeway_seconds: u64, // Clock skew allowed on exp and nbf
+    #[serde(default)]
+    claims: Vec<String>, // Claims copied into the event envelope
+    #[serde(skip)]
+    keys: Arc<RwLock<KeyCache>>,
+}
+
+fn default_header() -> String {
+    actix_web::http::header::AUTHORIZATION.to_string()
+}
+
+fn default_refresh_seconds() -> u64 {
+    3600
+}
+
+fn default_leeway_seconds() -> u64 {
+    60
+}
+
+/// Accept either a single string or a list of strings
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+impl JwtConfig {
+    pub fn get_header(&self) -> String {
+        self.header.clone()
+    }
+
+    pub fn get_jwks_file(&self) -> Option<String> {
+        self.jwks_file.clone()
+    }
+
+    pub fn get_jwks_url(&self) -> Option<String> {
+        self.jwks_url.clone()
+    }
+
+    pub fn get_audience(&self) -> &[String] {
+        &self.audience
+    }
+
+    pub fn get_issuer(&self) -> &[String] {
+        &self.issuer
+    }
+
+    /// Read the keys of `jwks_file`; keys from `jwks_url` are fetched on first use
+    pub fn load_keys(&mut self) -> Result<(), String> {
+        let Some(path) = &self.jwks_file else {
+            return Ok(());
+        };
+
+        let raw = fs::read_to_string(path).map_err(|e| format!("Failed to read JWKS file {}: {}", path, e))?;
+        let keys = parse_jwks(&raw).map_err(|e| format!("{} in JWKS file {}", e, path))?;
+        self.keys = Arc::new(RwLock::new(KeyCache { keys, fetched_at: Some(Instant::now()) }));
+        Ok(())
+    }
+
+    /// Verify the bearer token of a request and return the configured claims
+    pub async fn verify_request(&self, req: &HttpRequest) -> Result<BTreeMap<String, Value>, Error> {
+        let unauthorized = |reason: String| {
+            error!("JWT verification failed: {}", reason);
+            actix_web::error::ErrorUnauthorized("Invalid bearer token")
+        };
+
+        let value = req.headers()
+            .get(&self.header)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| unauthorized(format!("missing {} header", self.header)))?;
+        let token = bearer_token(value);
+
+        let claims = self.verify_token(token).await.map_err(unauthorized)?;
+        Ok(self.claims.iter()
+            .filter_map(|name| Some((name.clone(), claims.get(name)?.clone())))
+            .collect())
+    }
+
+    /// Check a token's signature, `aud`, `iss` and `exp`, returning all of its claims
+    pub async fn verify_token(&self, token: &str) -> Result<Map<String, Value>, String> {
+        let header = decode_header(token).map_err(|e| format!("malformed token: {}", e))?;
+        if !ASYMMETRIC_ALGORITHMS.contains(&header.alg) {
+            return Err(format!("algorithm {:?} is not accepted", header.alg));
+        }
+
+        if self.refetch_due(Duration::from_secs(self.refresh_seconds)) {
+            self.refresh_keys().await;
+        }
+
+        let mut candidates = self.candidate_keys(header.kid.as_deref(), header.alg);
+        // An unknown key id usually means the provider rotated its keys
+        if candidates.is_empty() && self.refetch_due(MIN_REFETCH_INTERVAL) {
+            self.refresh_keys().await;
+            candidates = self.candidate_keys(header.kid.as_deref(), header.alg);
+        }
+        if candidates.is_empty() {
+            return Err(format!("no key matches kid {:?}", header.kid));
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&self.audience);
+        validation.set_issuer(&self.issuer);
+        validation.set_required_spec_claims(&["exp", "aud", "iss"]);
+        validation.leeway = self.leeway_seconds;
+
+        let mut last_error = String::new();
+        for key in candidates {
+            match decode::<Map<String, Value>>(token, &key, &validation) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Keys that may have signed a token: the one named by `kid`, or else every key usable with `alg`
+    fn candidate_keys(&self, kid: Option<&str>, alg: Algorithm) -> Vec<DecodingKey> {
+        let Ok(cache) = self.keys.read() else {
+            return Vec::new();
+        };
+
+        cache.keys.keys
+            .iter()
+            .filter(|jwk| match kid {
+                Some(kid) => jwk.common.key_id.as_deref() == Some(kid),
+                None => true,
+            })
+            .filter(|jwk| key_allows(jwk, alg))
+            .filter_map(|jwk| DecodingKey::from_jwk(jwk).ok())
+            .collect()
+    }
+
+    /// Whether keys from `jwks_url` are older than `interval`, or were never fetched
+    fn refetch_due(&self, interval: Duration) -> bool {
+        self.jwks_url.is_some()
+            && self.keys.read().is_ok_and(|cache| cache.fetched_at.is_none_or(|at| at.elapsed() >= interval))
+    }
+
+    /// Fetch `jwks_url` again, keeping the current keys if that fails
+    async fn refresh_keys(&self) {
+        let Some(url) = &self.jwks_url else {
+            return;
+        };
+
+        match fetch_jwks(url).await {
+            Ok(keys) => {
+                info!("Fetched {} key(s) from {}", keys.keys.len(), url);
+                if let Ok(mut cache) = self.keys.write() {
+                    *cache = KeyCache { keys, fetched_at: Some(Instant::now()) };
+                }
+            }
+            Err(e) => {
+                warn!("Failed to fetch JWKS from {}: {}", url, e);
+                // Wait for the refetch interval before trying again
+                if let Ok(mut cache) = self.keys.write() {
+                    cache.fetched_at = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    /// Serve keys from `keys` instead of the configured source
+    #[cfg(test)]
+    pub fn set_keys(&mut self, keys: JwkSet) {
+        self.keys = Arc::new(RwLock::new(KeyCache { keys, fetched_at: Some(Instant::now()) }));
+    }
+}
+
+/// Strip the `Bearer ` scheme, if present
+fn bearer_token(value: &str) -> &str {
+    let value = value.trim();
+    match value.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+        _ => value,
+    }
+}
+
+/// A key pinned to an algorithm with `alg` may only verify that algorithm
+fn key_allows(jwk: &Jwk, alg: Algorithm) -> bool {
+    jwk.common.key_algorithm.is_none_or(|key_alg| signing_algorithm(key_alg) == Some(alg))
+}
+
+/// The signing algorithm a JWK `alg` stands for, or `None` for encryption algorithms
+fn signing_algorithm(key_alg: KeyAlgorithm) -> Option<Algorithm> {
+    match key_alg {
+        KeyAlgorithm::HS256 => Some(Algorithm::HS256),
+        KeyAlgorithm::HS384 => Some(Algorithm::HS384),
+        KeyAlgorithm::HS512 => Some(Algorithm::HS512),
+        KeyAlgorithm::ES256 => Some(Algorithm::ES256),
+        KeyAlgorithm::ES384 => Some(Algorithm::ES384),
+        KeyAlgorithm::RS256 => Some(Algorithm::RS256),
+        KeyAlgorithm::RS384 => Some(Algorithm::RS384),
+        KeyAlgorithm::RS512 => Some(Algorithm::RS512),
+        KeyAlgorithm::PS256 => Some(Algorithm::PS256),
+        KeyAlgorithm::PS384 => Some(Algorithm::PS384),
+        KeyAlgorithm::PS512 => Some(Algorithm::PS512),
+        KeyAlgorithm::EdDSA => Some(Algorithm::EdDSA),
+        KeyAlgorithm::RSA1_5 | KeyAlgorithm::RSA_OAEP | KeyAlgorithm::RSA_OAEP_256 => None,
+    }
+}
+
+pub fn parse_jwks(raw: &str) -> Result<JwkSet, String> {
+    let keys: JwkSet = serde_json::from_str(raw).map_err(|e| format!("Invalid JWKS: {}", e))?;
+    if keys.keys.is_empty() {
+        return Err("No keys found".to_string());
+    }
+    Ok(keys)
+}
+
+async fn fetch_jwks(url: &str) -> Result<JwkSet, String> {
+    let client = reqwest::Client::builder()
+        .timeout(JWKS_FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client.get(url).send().await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    parse_jwks(&body)
+}
@@ -0,0 +1,220 @@
+// lockout.rs
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use actix_web::web;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use moka::future::Cache as MokaCache;
+use serde::Serialize;
+
+pub mod test;
+
+/// Log target of audit records, so they can be routed apart from the service log
+pub const AUDIT_TARGET: &str = "audit";
+
+/// The delay on a failed attempt never grows past this
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Reason a request failed authentication, left in the request extensions by the check that rejected it
+#[derive(Debug, Clone, Copy)]
+pub struct AuthFailure(pub &'static str);
+
+/// How failed authentication attempts are slowed down and banned
+#[derive(Debug, Clone)]
+pub struct LockoutSettings {
+    threshold: u32,    // Failures in the window that trigger a ban, 0 turns the lockout off
+    window: Duration,  // Failures older than this are forgotten
+    delay: Duration,   // Delay on the first failure, doubled on each further one
+    ban: Duration,     // Length of the first ban, doubled on each further one
+    max_ban: Duration,
+    audit_file: Option<String>, // JSON lines appended for every failure
+}
+
+impl LockoutSettings {
+    pub fn from_env() -> Self {
+        let env_u64 = |var: &str, default: u64| {
+            env::var(var).ok().and_then(|val| val.parse::<u64>().ok()).unwrap_or(default)
+        };
+
+        Self {
+            threshold: env_u64("LOCKOUT_THRESHOLD", 5) as u32,
+            window: Duration::from_secs(env_u64("LOCKOUT_WINDOW", 600)),
+            delay: Duration::from_millis(env_u64("LOCKOUT_DELAY_MS", 100)),
+            ban: Duration::from_secs(env_u64("LOCKOUT_BAN_SECONDS", 300)),
+            max_ban: Duration::from_secs(env_u64("LOCKOUT_MAX_BAN_SECONDS", 86_400)),
+            audit_file: env::var("AUDIT_LOG_FILE").ok(),
+        }
+    }
+}
+
+/// Failures recorded for one source address
+#[derive(Debug, Clone, Default)]
+struct FailureState {
+    failures: u32,
+    last_failure: Option<DateTime<Utc>>,
+    bans: u32,
+    banned_until: Option<DateTime<Utc>>,
+    last_path: String,
+    last_reason: String,
+}
+
+impl FailureState {
+    fn is_banned(&self, now: DateTime<Utc>) -> bool {
+        self.banned_until.is_some_and(|until| until > now)
+    }
+
+    /// Count a failure, starting a ban once the threshold is reached
+    fn register(&mut self, now: DateTime<Utc>, settings: &LockoutSettings, path: &str, reason: &str) {
+        let window = chrono::Duration::from_std(settings.window).unwrap_or(chrono::Duration::MAX);
+        if self.last_failure.is_some_and(|last| now - last > window) {
+            self.failures = 0;
+        }
+
+        self.failures += 1;
+        self.last_failure = Some(now);
+        self.last_path = path.to_string();
+        self.last_reason = reason.to_string();
+
+        if self.failures >= settings.threshold {
+            self.bans += 1;
+            let ban = doubled(settings.ban, self.bans, settings.max_ban);
+            self.banned_until = Some(now + chrono::Duration::from_std(ban).unwrap_or(chrono::Duration::MAX));
+            self.failures = 0;
+        }
+    }
+}
+
+/// `base` doubled for every step after the first, up to `max`
+fn doubled(base: Duration, step: u32, max: Duration) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(step.saturating_sub(1))).min(max)
+}
+
+/// One failed authentication attempt, as written to the audit trail
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    timestamp: String,
+    ip: Option<String>,
+    path: String,
+    reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    banned_until: Option<String>,
+}
+
+/// A source address that is currently banned, as listed by the admin endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct Ban {
+    ip: String,
+    banned_until: String,
+    bans: u32,
+    last_path: String,
+    last_reason: String,
+}
+
+/// Failed verification and polling attempts per source address, kept in a bounded cache
+#[derive(Debug, Clone)]
+pub struct FailureTracker {
+    settings: LockoutSettings,
+    sources: Arc<MokaCache<IpAddr, FailureState>>,
+}
+
+impl FailureTracker {
+    pub fn new(settings: LockoutSettings) -> Self {
+        // Keep a source long enough to remember its failures and escalate its next ban
+        let sources = Arc::new(
+            MokaCache::builder()
+                .max_capacity(100_000)
+                .time_to_idle(settings.window.max(settings.max_ban))
+                .build()
+        );
+
+        Self { settings, sources }
+    }
+
+    /// End of the ban on a source address, while it lasts
+    pub async fn banned_until(&self, ip: IpAddr) -> Option<DateTime<Utc>> {
+        let state = self.sources.get(&ip).await?;
+        state.banned_until.filter(|_| state.is_banned(Utc::now()))
+    }
+
+    /// Record a failed attempt and write its audit record, returning how long to wait before answering
+    pub async fn record_failure(&self, ip: Option<IpAddr>, path: &str, reason: &str) -> Duration {
+        let now = Utc::now();
+
+        let state = match ip {
+            Some(ip) if self.settings.threshold > 0 => {
+                let settings = self.settings.clone();
+                let entry = self.sources
+                    .entry(ip)
+                    .and_upsert_with(|existing| {
+                        let mut state = existing.map(|entry| entry.into_value()).unwrap_or_default();
+                        state.register(now, &settings, path, reason);
+                        std::future::ready(state)
+                    })
+                    .await;
+                Some(entry.into_value())
+            }
+            _ => None,
+        };
+
+        let banned_until = state.as_ref().and_then(|state| state.banned_until.filter(|_| state.is_banned(now)));
+        self.audit(&AuditRecord {
+            timestamp: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            ip: ip.map(|ip| ip.to_string()),
+            path: path.to_string(),
+            reason: reason.to_string(),
+            banned_until: banned_until.map(|until| until.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        }).await;
+
+        match state {
+            // The attempt that starts a ban is answered at once, later ones get a 429
+            Some(state) if state.failures > 0 => doubled(self.settings.delay, state.failures, MAX_DELAY),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Sources that are banned right now, sorted by address
+    pub fn current_bans(&self) -> Vec<Ban> {
+        let now = Utc::now();
+        let mut bans: Vec<_> = self.sources
+            .iter()
+            .filter(|(_, state)| state.is_banned(now))
+            .map(|(ip, state)| (*ip, state))
+            .collect();
+        bans.sort_by_key(|(ip, _)| *ip);
+
+        bans.into_iter()
+            .filter_map(|(ip, state)| Some(Ban {
+                ip: ip.to_string(),
+                banned_until: state.banned_until?.to_rfc3339_opts(SecondsFormat::Secs, true),
+                bans: state.bans,
+                last_path: state.last_path,
+                last_reason: state.last_reason,
+            }))
+            .collect()
+    }
+
+    /// Log an audit record and append it to the audit file
+    async fn audit(&self, record: &AuditRecord) {
+        let line = serde_json::to_string(record).unwrap_or_default();
+        warn!(target: AUDIT_TARGET, "{}", line);
+
+        if let Some(path) = self.settings.audit_file.clone() {
+            let file_path = path.clone();
+            let written = web::block(move || {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&file_path)
+                    .and_then(|mut file| writeln!(file, "{}", line))
+            }).await;
+
+            if let Err(e) = written.map_err(std::io::Error::other).and_then(|written| written) {
+                warn!("Failed to write audit record to {}: {}", path, e);
+            }
+        }
+    }
+}
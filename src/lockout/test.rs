@@ -0,0 +1,183 @@
+#[allow(unused_imports)]
+use super::*;
+
+#[cfg(test)]
+mod test_lockout {
+    use super::*;
+    use crate::cache::OrderedCache;
+    use crate::endpoint_handler::{bans_handler, data_retrieval_handler_with_auth, verification_endpoint_handler};
+    use crate::token_set::TokenSet;
+    use crate::webhook_config::WebhookConfig;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+
+    fn settings(threshold: u32) -> LockoutSettings {
+        LockoutSettings {
+            threshold,
+            window: Duration::from_secs(600),
+            delay: Duration::ZERO,
+            ban: Duration::from_secs(60),
+            max_ban: Duration::from_secs(300),
+            audit_file: None,
+        }
+    }
+
+    fn ip(source: &str) -> IpAddr {
+        source.parse().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_delay_doubles_until_a_ban() {
+        let tracker = FailureTracker::new(LockoutSettings { delay: Duration::from_millis(100), ..settings(3) });
+        let source = Some(ip("198.51.100.7"));
+
+        assert_eq!(tracker.record_failure(source, "/pollhook/events", "invalid polling token").await, Duration::from_millis(100));
+        assert_eq!(tracker.record_failure(source, "/pollhook/events", "invalid polling token").await, Duration::from_millis(200));
+        assert!(tracker.banned_until(ip("198.51.100.7")).await.is_none());
+
+        assert_eq!(tracker.record_failure(source, "/pollhook/events", "invalid polling token").await, Duration::ZERO);
+        let banned_until = tracker.banned_until(ip("198.51.100.7")).await.unwrap();
+        assert!((banned_until - Utc::now()).num_seconds() > 50);
+
+        // Other sources and unknown sources are never banned
+        tracker.record_failure(None, "/pollhook/events", "invalid polling token").await;
+        assert!(tracker.banned_until(ip("203.0.113.9")).await.is_none());
+
+        let bans = serde_json::to_value(tracker.current_bans()).unwrap();
+        assert_eq!(bans.as_array().unwrap().len(), 1);
+        assert_eq!(bans[0]["ip"], "198.51.100.7");
+        assert_eq!(bans[0]["last_reason"], "invalid polling token");
+    }
+
+    #[actix_web::test]
+    async fn test_bans_escalate_and_old_failures_are_forgotten() {
+        let settings = settings(2);
+        let start = Utc::now();
+        let mut state = FailureState::default();
+
+        state.register(start, &settings, "/callhook/meta", "invalid token");
+        // Outside the window the first failure no longer counts
+        state.register(start + chrono::Duration::seconds(601), &settings, "/callhook/meta", "invalid token");
+        assert!(!state.is_banned(start + chrono::Duration::seconds(601)));
+
+        let now = start + chrono::Duration::seconds(602);
+        state.register(now, &settings, "/callhook/meta", "invalid token");
+        assert_eq!(state.banned_until, Some(now + chrono::Duration::seconds(60)));
+
+        state.register(now, &settings, "/callhook/meta", "invalid token");
+        state.register(now, &settings, "/callhook/meta", "invalid token");
+        assert_eq!(state.banned_until, Some(now + chrono::Duration::seconds(120)));
+
+        for _ in 0..6 {
+            state.register(now, &settings, "/callhook/meta", "invalid token");
+        }
+        assert_eq!(state.banned_until, Some(now + chrono::Duration::seconds(300)));
+    }
+
+    #[actix_web::test]
+    async fn test_audit_records_are_appended() {
+        let path = std::env::temp_dir().join(format!("pollhook-audit-{}.log", std::process::id()));
+        let tracker = FailureTracker::new(LockoutSettings {
+            audit_file: Some(path.to_str().unwrap().to_string()),
+            ..settings(2)
+        });
+
+        tracker.record_failure(Some(ip("198.51.100.7")), "/callhook/meta", "invalid token").await;
+        tracker.record_failure(Some(ip("198.51.100.7")), "/callhook/meta", "missing token").await;
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<serde_json::Value> = raw.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["ip"], "198.51.100.7");
+        assert_eq!(records[0]["path"], "/callhook/meta");
+        assert_eq!(records[0]["reason"], "invalid token");
+        assert!(records[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(records[0].get("banned_until").is_none());
+        assert!(records[1]["banned_until"].is_string());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_polling_source_is_banned() {
+        let config: WebhookConfig = serde_yaml::from_str("verification: {}\ndata: {}\n").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(OrderedCache::new(vec!["events".to_string()])))
+                .app_data(web::Data::new(TokenSet::parse("polling-secret").unwrap()))
+                .app_data(web::Data::new(FailureTracker::new(settings(2))))
+                .route("/pollhook/{alias}", web::get().to(data_retrieval_handler_with_auth))
+        ).await;
+
+        let poll = |token: &str| test::TestRequest::get()
+            .uri("/pollhook/events")
+            .peer_addr("198.51.100.7:40000".parse().unwrap())
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+
+        assert_eq!(test::call_service(&app, poll("guess-1")).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::call_service(&app, poll("guess-2")).await.status(), StatusCode::UNAUTHORIZED);
+
+        // Banned, even with the right token
+        let response = test::call_service(&app, poll("polling-secret")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[actix_web::test]
+    async fn test_verification_failures_are_listed_as_bans() {
+        unsafe {
+            std::env::set_var("TEST_LOCKOUT_VERIFY_TOKEN", "verify-secret");
+        }
+        let mut config: WebhookConfig = serde_yaml::from_str(r#"
+verification:
+  path: /callhook/meta
+  token_env: TEST_LOCKOUT_VERIFY_TOKEN
+  token:
+    in: query
+    locate: hub.verify_token
+  challenge:
+    in: query
+    locate: hub.challenge
+  response:
+    type: text/plain
+    data: "@challenge"
+data: {}
+"#).unwrap();
+        config.load_verification_tokens().unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(FailureTracker::new(settings(2))))
+                .route("/pollhook/_admin/bans", web::get().to(|req, config, lockout| {
+                    bans_handler(req, config, lockout, TokenSet::parse("admin-secret").unwrap())
+                }))
+                .route("/callhook/{path:.*}", web::get().to(|req, payload, config, lockout| {
+                    verification_endpoint_handler(req, payload, "default".to_string(), config, lockout)
+                }))
+        ).await;
+
+        let verify = |token: &str| test::TestRequest::get()
+            .uri(&format!("/callhook/meta?hub.verify_token={}&hub.challenge=42", token))
+            .peer_addr("198.51.100.7:40000".parse().unwrap())
+            .to_request();
+
+        assert_eq!(test::call_service(&app, verify("verify-secret")).await.status(), StatusCode::OK);
+        assert_eq!(test::call_service(&app, verify("guess-1")).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(test::call_service(&app, verify("guess-2")).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(test::call_service(&app, verify("verify-secret")).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let req = test::TestRequest::get()
+            .uri("/pollhook/_admin/bans")
+            .peer_addr("203.0.113.9:40000".parse().unwrap())
+            .insert_header((header::AUTHORIZATION, "Bearer admin-secret"))
+            .to_request();
+        let listed: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(listed["count"], 1);
+        assert_eq!(listed["bans"][0]["ip"], "198.51.100.7");
+        assert_eq!(listed["bans"][0]["last_path"], "/callhook/meta");
+        assert_eq!(listed["bans"][0]["last_reason"], "invalid token");
+    }
+}
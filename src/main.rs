@@ -4,24 +4,34 @@ mod data_handler;
 mod endpoint_handler;
 mod cache;
 mod polling_handler;
+mod body_format;
+mod config_validation;
+mod token_set;
+mod path_pattern;
+mod jwt_auth;
+mod client_cert;
+mod source_ip;
+mod lockout;
 
 use verification_handler::verification_config;
 use webhook_config::WebhookConfig;
 
 use actix_web::{web, App, HttpServer, guard};
-use serde_yaml;
 use std::{env, path::Path};
 use cache::OrderedCache;
+use data_handler::replay_guard::ReplayGuard;
+use lockout::{FailureTracker, LockoutSettings};
+use token_set::TokenSet;
 use std::fs;
-use log::info;
+use log::{error, info, warn};
 use dotenv::dotenv;
-use env_logger;
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use rustls::{Certificate, PrivateKey, ServerConfig};
 use std::io::BufReader;
 
 pub const CALLBACK_PATH: &str = "callhook";
 pub const POLLING_PATH: &str = "pollhook";
+pub const ADMIN_BANS_PATH: &str = "_admin/bans";
 
 fn read_config(file_path: &str) -> Result<WebhookConfig, Box<dyn std::error::Error>> {
     let config_str = fs::read_to_string(file_path)?;
@@ -48,25 +58,49 @@ fn load_rustls_config() -> Result<ServerConfig, Box<dyn std::error::Error + Send
 
     let key = PrivateKey(keys.remove(0));
 
-    let config = ServerConfig::builder()
-        .with_safe_defaults()
-        .with_no_client_auth()
+    let config = client_cert::tls_config_builder()?
         .with_single_cert(cert_chain, key)?;
 
     Ok(config)
 }
 
+/// Run `validate [config path]`: report every config issue without starting the server
+fn validate_command(config_path: &str) -> i32 {
+    let config = match read_config(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("error: {}: {}", config_path, e);
+            return 1;
+        }
+    };
+
+    let issues = config_validation::validate_config(&config);
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    let errors = config_validation::count_errors(&issues);
+    println!("{}: {} error(s), {} warning(s)", config_path, errors, issues.len() - errors);
+    if errors > 0 { 1 } else { 0 }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Load environment variables from .env file
     dotenv().ok();
 
-    // Initialize logger
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
-
     // Get configuration file path from environment variable or use default
     let config_path = env::var("CONFIG_FILE_PATH").unwrap_or_else(|_| "config_webhook.yaml".to_string());
 
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("validate") {
+        let config_path = args.get(2).cloned().unwrap_or(config_path);
+        std::process::exit(validate_command(&config_path));
+    }
+
+    // Initialize logger
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
     // Check if the config file exists
     if !Path::new(&config_path).exists() {
         return Err(std::io::Error::new(
@@ -77,10 +111,33 @@ async fn main() -> std::io::Result<()> {
 
     let mut config = read_config(&config_path).expect("Failed to read config file");
 
-    let verification_token = env::var("VERIFY_TOKEN").expect("VERIFY_TOKEN is not set");
-    let data_retrieve_token = env::var("DATA_RETRIEVE_TOKEN").expect("DATA_RETRIEVE_TOKEN is not set");
+    // Refuse to start on a config that would silently misroute or reject requests
+    let issues = config_validation::validate_config(&config);
+    for issue in &issues {
+        match issue.get_severity() {
+            config_validation::Severity::Error => error!("{}: {}", issue.get_location(), issue.get_message()),
+            config_validation::Severity::Warning => warn!("{}: {}", issue.get_location(), issue.get_message()),
+        }
+    }
+    let errors = config_validation::count_errors(&issues);
+    if errors > 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Config validation failed with {} error(s)", errors)
+        ));
+    }
+
+    let data_retrieve_tokens = TokenSet::from_env("DATA_RETRIEVE_TOKEN").expect("Failed to load polling tokens");
 
-    config.set_token(verification_token);
+    // The bans endpoint is only served when an admin token is configured
+    let admin_tokens = TokenSet::is_configured("ADMIN_TOKEN")
+        .then(|| TokenSet::from_env("ADMIN_TOKEN").expect("Failed to load admin tokens"));
+
+    config.load_verification_tokens().expect("Failed to load verification tokens");
+    config.load_signature_secrets().expect("Failed to load signature secrets");
+    config.load_template_secrets().expect("Failed to load response template secrets");
+    config.load_jwks().expect("Failed to load JWKS files");
+    config.load_ip_allowlists().expect("Failed to load IP allowlist files");
     config.init_polling_config();
 
     // Get port from environment or use default
@@ -89,11 +146,12 @@ async fn main() -> std::io::Result<()> {
     info!("Starting webhook verification server on port {}", port);
     info!("Using config file: {}", config_path);
 
-    let method = actix_web::http::Method::try_from(config.get_verification_config().get_verification_method().as_str())
-        .unwrap_or(actix_web::http::Method::GET);
+    let verification_routes = config.get_verification_map().get_name_method_config_vec();
 
-    let data_routes = config.get_data_config().get_alias_path_method_vec();
+    let data_routes = config.get_data_config().get_alias_method_pattern_vec();
     let ordered_cache = OrderedCache::new(data_routes.iter().map(|t| t.0.clone()).collect());
+    let replay_guard = ReplayGuard::new(config.get_data_config().get_replay_windows());
+    let lockout = FailureTracker::new(LockoutSettings::from_env());
 
     // Check if HTTPS should be used
     let use_https =
@@ -101,43 +159,67 @@ async fn main() -> std::io::Result<()> {
             (env::var("SSL_CERT_FILE").is_ok() && env::var("SSL_KEY_FILE").is_ok());
 
     let server = HttpServer::new(move || {
-        let method = method.clone();
-
         let mut app = App::new()
             .app_data(web::Data::new(config.clone()))
             .app_data(web::Data::new(ordered_cache.clone()))
-            .app_data(web::Data::new(data_retrieve_token.clone()))
-            .route(
+            .app_data(web::Data::new(replay_guard.clone()))
+            .app_data(web::Data::new(lockout.clone()))
+            .app_data(web::Data::new(data_retrieve_tokens.clone()));
+
+        // Add one route per verification block, each guarded by its own method and path
+        for (name, method_str, verification_config) in &verification_routes {
+            let name_clone = name.clone();
+            let verification_config = verification_config.clone();
+            // Invalid methods fail validation, so the server never gets this far with one
+            let method = actix_web::http::Method::try_from(method_str.as_str())
+                .expect("verification method was validated at startup");
+
+            app = app.route(
                 &format!("/{}/{{path:.*}}", CALLBACK_PATH),
                 web::route()
-                    .guard(guard::fn_guard(move |ctx| ctx.head().method == method))
-                    .to(endpoint_handler::verification_endpoint_handler),
-            )
-            // Health check route
-            .route("/health", web::get().to(endpoint_handler::health_check_handler));
-
-        // Add routes for each data endpoint (for receiving data)
-        for (alias, path, method_str) in &data_routes {
-            let route_path = if path.starts_with('/') {
-                path.clone()
-            } else {
-                format!("/{}", path)
-            };
+                    .guard(guard::fn_guard(move |ctx| {
+                        ctx.head().method == method
+                            && verification_config.is_verification_path(ctx.head().uri.path().to_string())
+                    }))
+                    .to(move |req, payload, config, lockout| {
+                        endpoint_handler::verification_endpoint_handler(req, payload, name_clone.clone(), config, lockout)
+                    }),
+            );
+        }
 
+        // Health check route
+        app = app.route("/health", web::get().to(endpoint_handler::health_check_handler));
+
+        // Add routes for each data endpoint (for receiving data), each guarded by its own method and path pattern
+        for (alias, method_str, path_pattern) in &data_routes {
             let alias_clone = alias.clone();
+            let path_pattern = path_pattern.clone();
             let method = actix_web::http::Method::try_from(method_str.as_str())
-                .unwrap_or(actix_web::http::Method::GET);
+                .expect("data method was validated at startup");
 
             app = app.route(
-                &route_path,
+                "/{path:.*}",
                 web::route()
-                    .guard(guard::fn_guard(move |ctx| ctx.head().method == method))
-                    .to(move |req, payload, config, cache| {
-                        endpoint_handler::data_endpoint_handler(req, payload, alias_clone.clone(), config, cache)
+                    .guard(guard::fn_guard(move |ctx| {
+                        ctx.head().method == method && path_pattern.matches(ctx.head().uri.path()).is_some()
+                    }))
+                    .to(move |req, payload, config, cache, replay_guard| {
+                        endpoint_handler::data_endpoint_handler(req, payload, alias_clone.clone(), config, cache, replay_guard)
                     }),
             );
         }
 
+        // Admin route listing banned sources, registered before the alias route
+        if let Some(admin_tokens) = &admin_tokens {
+            let admin_tokens = admin_tokens.clone();
+            app = app.route(
+                &format!("/{}/{}", POLLING_PATH, ADMIN_BANS_PATH),
+                web::get().to(move |req, config, lockout| {
+                    endpoint_handler::bans_handler(req, config, lockout, admin_tokens.clone())
+                }),
+            );
+        }
+
         // Add data retrieval route with authentication
         app = app.route(
             &format!("/{}/{{alias}}", POLLING_PATH),
@@ -145,7 +227,8 @@ async fn main() -> std::io::Result<()> {
         );
 
         app
-    });
+    })
+    .on_connect(client_cert::record_peer_certificate);
 
     if use_https {
         let rustls_config = if let (Ok(cert_file), Ok(key_file)) =
@@ -172,9 +255,8 @@ async fn main() -> std::io::Result<()> {
 
             let key = PrivateKey(keys.remove(0));
 
-            ServerConfig::builder()
-                .with_safe_defaults()
-                .with_no_client_auth()
+            client_cert::tls_config_builder()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
                 .with_single_cert(cert_chain, key)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
         } else {
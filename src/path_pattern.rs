@@ -0,0 +1,221 @@
+// path_pattern.rs
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+pub mod test;
+
+// Define an enum for the segments of a path pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String), // {name}: exactly one segment, captured
+    Single,        // *: exactly one segment
+    Multi,         // **: any number of segments, including none
+    Legacy,        // ...: one or more segments, or any number when only wildcards follow
+}
+
+/// One element of a pattern once `...` is expanded, used to compare patterns
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Atom<'a> {
+    Literal(&'a str),
+    One,  // Any single segment
+    Many, // Any number of segments
+}
+
+/// Path pattern compiled when the config is loaded, e.g. `/callhook/{tenant}/*/events/**`
+#[derive(Clone)]
+pub struct PathPattern {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl fmt::Debug for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PathPattern({:?})", self.source)
+    }
+}
+
+// Custom deserialization for PathPattern, so malformed patterns fail at config load
+impl<'de> Deserialize<'de> for PathPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        PathPattern::parse(&s).map_err(D::Error::custom)
+    }
+}
+
+impl PathPattern {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut names = HashSet::new();
+
+        for segment in source.split('/').filter(|s| !s.is_empty()) {
+            let segment = match segment {
+                "*" => Segment::Single,
+                "**" => Segment::Multi,
+                "..." => Segment::Legacy,
+                _ if segment.starts_with('{') && segment.ends_with('}') => {
+                    let name = &segment[1..segment.len() - 1];
+                    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        return Err(format!("Invalid path pattern {}: bad parameter name '{}'", source, segment));
+                    }
+                    if !names.insert(name.to_string()) {
+                        return Err(format!("Invalid path pattern {}: {{{}}} is used more than once", source, name));
+                    }
+                    Segment::Param(name.to_string())
+                }
+                _ if segment.contains(['{', '}', '*']) => {
+                    return Err(format!(
+                        "Invalid path pattern {}: wildcards and parameters must be a whole segment, got '{}'",
+                        source, segment
+                    ));
+                }
+                _ => Segment::Literal(segment.to_string()),
+            };
+            segments.push(segment);
+        }
+
+        Ok(Self { source: source.to_string(), segments })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Names of the `{name}` segments
+    pub fn param_names(&self) -> Vec<String> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Param(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// First segment, when it is a literal
+    pub fn first_literal(&self) -> Option<&str> {
+        match self.segments.first() {
+            Some(Segment::Literal(literal)) => Some(literal),
+            _ => None,
+        }
+    }
+
+    /// Match a request path, returning the named `{param}` segments
+    pub fn matches(&self, path: &str) -> Option<BTreeMap<String, String>> {
+        let request: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut failed = HashSet::new();
+        let mut params = BTreeMap::new();
+
+        self.match_from(0, &request, 0, &mut failed, &mut params).then_some(params)
+    }
+
+    /// Backtracking match that remembers failed positions, so stacked wildcards stay linear-ish
+    fn match_from(
+        &self,
+        index: usize,
+        request: &[&str],
+        position: usize,
+        failed: &mut HashSet<(usize, usize)>,
+        params: &mut BTreeMap<String, String>,
+    ) -> bool {
+        if failed.contains(&(index, position)) {
+            return false;
+        }
+
+        let remaining = &request[position..];
+        let matched = match self.segments.get(index) {
+            None => remaining.is_empty(),
+            Some(Segment::Multi) => (position..=request.len())
+                .any(|next| self.match_from(index + 1, request, next, failed, params)),
+            Some(Segment::Legacy) if self.only_wildcards_from(index) => true,
+            Some(Segment::Legacy) => (position + 1..=request.len())
+                .any(|next| self.match_from(index + 1, request, next, failed, params)),
+            Some(segment) => match remaining.first() {
+                None => false,
+                Some(value) => {
+                    let fits = match segment {
+                        Segment::Literal(literal) => literal == value,
+                        _ => true,
+                    };
+                    let matched = fits && self.match_from(index + 1, request, position + 1, failed, params);
+                    if matched && let Segment::Param(name) = segment {
+                        params.insert(name.clone(), value.to_string());
+                    }
+                    matched
+                }
+            },
+        };
+
+        if !matched {
+            failed.insert((index, position));
+        }
+        matched
+    }
+
+    /// A trailing run of `...` also matches no segments at all
+    fn only_wildcards_from(&self, index: usize) -> bool {
+        self.segments[index..].iter().all(|segment| *segment == Segment::Legacy)
+    }
+
+    fn atoms(&self) -> Vec<Atom<'_>> {
+        let mut atoms = Vec::new();
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => atoms.push(Atom::Literal(literal)),
+                Segment::Param(_) | Segment::Single => atoms.push(Atom::One),
+                Segment::Multi => atoms.push(Atom::Many),
+                Segment::Legacy if self.only_wildcards_from(index) => atoms.push(Atom::Many),
+                Segment::Legacy => atoms.extend([Atom::One, Atom::Many]),
+            }
+        }
+        atoms
+    }
+
+    /// Whether some request path matches both patterns
+    pub fn overlaps(&self, other: &PathPattern) -> bool {
+        fn overlap(a: &[Atom], b: &[Atom], i: usize, j: usize, memo: &mut HashMap<(usize, usize), bool>) -> bool {
+            if let Some(&known) = memo.get(&(i, j)) {
+                return known;
+            }
+
+            let result = match (a.get(i), b.get(j)) {
+                (None, None) => true,
+                (Some(Atom::Many), _) if overlap(a, b, i + 1, j, memo) => true,
+                (_, Some(Atom::Many)) if overlap(a, b, i, j + 1, memo) => true,
+                // A `**` can take the segment the other pattern is at, and stay
+                (Some(Atom::Many), Some(_)) => overlap(a, b, i, j + 1, memo),
+                (Some(_), Some(Atom::Many)) => overlap(a, b, i + 1, j, memo),
+                (Some(Atom::Literal(x)), Some(Atom::Literal(y))) => x == y && overlap(a, b, i + 1, j + 1, memo),
+                (Some(_), Some(_)) => overlap(a, b, i + 1, j + 1, memo),
+                _ => false,
+            };
+
+            memo.insert((i, j), result);
+            result
+        }
+
+        overlap(&self.atoms(), &other.atoms(), 0, 0, &mut HashMap::new())
+    }
+
+    /// Two variable-length wildcards with no literal segment between them can split a path
+    /// in more than one way, so it is unclear which segments each one took
+    pub fn is_ambiguous(&self) -> bool {
+        let mut open_wildcard = false;
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(_) => open_wildcard = false,
+                Segment::Multi | Segment::Legacy if open_wildcard && !self.only_wildcards_from(index) => return true,
+                Segment::Multi | Segment::Legacy => open_wildcard = true,
+                Segment::Param(_) | Segment::Single => {}
+            }
+        }
+
+        false
+    }
+}
@@ -0,0 +1,90 @@
+#[allow(unused_imports)]
+use super::*;
+
+#[cfg(test)]
+mod test_path_pattern {
+    use super::*;
+
+    fn pattern(source: &str) -> PathPattern {
+        PathPattern::parse(source).unwrap()
+    }
+
+    #[test]
+    fn test_single_and_multi_segment_wildcards() {
+        let single = pattern("/callhook/*/events");
+        assert!(single.matches("/callhook/acme/events").is_some());
+        assert!(single.matches("/callhook/events").is_none());
+        assert!(single.matches("/callhook/acme/eu/events").is_none());
+
+        let multi = pattern("/callhook/**/events");
+        assert!(multi.matches("/callhook/events").is_some());
+        assert!(multi.matches("/callhook/acme/events").is_some());
+        assert!(multi.matches("/callhook/acme/eu/events").is_some());
+        assert!(multi.matches("/callhook/acme/eu").is_none());
+
+        let trailing = pattern("/hooks/**");
+        assert!(trailing.matches("/hooks").is_some());
+        assert!(trailing.matches("/hooks/a/b/c").is_some());
+        assert!(trailing.matches("/other").is_none());
+    }
+
+    #[test]
+    fn test_legacy_wildcard() {
+        // A middle `...` takes at least one segment, a trailing one may take none
+        let middle = pattern("/callhook/.../callback");
+        assert!(middle.matches("/callhook/a/callback").is_some());
+        assert!(middle.matches("/callhook/a/b/callback").is_some());
+        assert!(middle.matches("/callhook/callback").is_none());
+
+        let trailing = pattern("/callhook/endpoint/...");
+        assert!(trailing.matches("/callhook/endpoint").is_some());
+        assert!(trailing.matches("/callhook/endpoint/a/b").is_some());
+    }
+
+    #[test]
+    fn test_params_are_captured() {
+        let tenant = pattern("/callhook/{tenant}/**/{event}");
+        let params = tenant.matches("/callhook/acme/eu/west/order.created").unwrap();
+
+        assert_eq!(params.get("tenant").map(String::as_str), Some("acme"));
+        assert_eq!(params.get("event").map(String::as_str), Some("order.created"));
+        assert_eq!(tenant.param_names(), vec!["tenant", "event"]);
+        assert!(tenant.matches("/callhook/acme").is_none());
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(PathPattern::parse("/callhook/{}").is_err());
+        assert!(PathPattern::parse("/callhook/{a b}").is_err());
+        assert!(PathPattern::parse("/callhook/{id}/{id}").is_err());
+        assert!(PathPattern::parse("/callhook/file*.json").is_err());
+        assert!(PathPattern::parse("/callhook/pre{id}").is_err());
+        assert!(serde_yaml::from_str::<PathPattern>("/callhook/***").is_err());
+        assert!(serde_yaml::from_str::<PathPattern>("/callhook/{id}").is_ok());
+    }
+
+    #[test]
+    fn test_overlaps() {
+        assert!(pattern("/callhook/*/events").overlaps(&pattern("/callhook/acme/*")));
+        assert!(pattern("/callhook/**").overlaps(&pattern("/callhook")));
+        assert!(pattern("/callhook/{tenant}").overlaps(&pattern("/callhook/*")));
+        assert!(pattern("/a/**/z").overlaps(&pattern("/a/b/**")));
+        assert!(pattern("/callhook/.../callback").overlaps(&pattern("/callhook/x/y/callback")));
+
+        assert!(!pattern("/callhook/*/events").overlaps(&pattern("/callhook/*/orders")));
+        assert!(!pattern("/callhook/*").overlaps(&pattern("/callhook/a/b")));
+        assert!(!pattern("/callhook/.../callback").overlaps(&pattern("/callhook/callback")));
+        assert!(!pattern("/pollhook/*").overlaps(&pattern("/pollhook")));
+    }
+
+    #[test]
+    fn test_ambiguous_patterns() {
+        assert!(pattern("/a/**/**").is_ambiguous());
+        assert!(pattern("/a/**/{id}/**/b").is_ambiguous());
+        assert!(pattern("/a/.../**").is_ambiguous());
+
+        assert!(!pattern("/a/**/b/**").is_ambiguous());
+        assert!(!pattern("/a/*/*").is_ambiguous());
+        assert!(!pattern("/a/.../...").is_ambiguous());
+    }
+}
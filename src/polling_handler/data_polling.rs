@@ -10,6 +10,7 @@ pub struct DataResponse {
     pub success: bool,
     pub message: String,
     pub count: usize,
+    pub ids: Vec<String>, // Event ID of each item in `data`, in the same order
     pub data: Vec<serde_json::Value>,
 }
 
@@ -25,6 +26,7 @@ pub async fn retrieve_data_with_polling(
             success: false,
             message: format!("Alias '{}' not found", alias),
             count: 0,
+            ids: Vec::new(),
             data: Vec::new(),
         });
     }
@@ -34,11 +36,12 @@ pub async fn retrieve_data_with_polling(
     // Long polling with timeout
     match timeout(polling_config.get_timeout(), poll_for_data(cache, alias, &max_polled_item)).await {
         Ok(Ok(data_items)) if !data_items.is_empty() => {
-            let values: Vec<JsonValue> = data_items.into_iter().map(|(_, v)| v).collect();
+            let (ids, values): (Vec<String>, Vec<JsonValue>) = data_items.into_iter().unzip();
             Ok(DataResponse {
                 success: true,
                 message: format!("Retrieved {} items after polling", values.len()),
                 count: values.len(),
+                ids,
                 data: values,
             })
         }
@@ -47,6 +50,7 @@ pub async fn retrieve_data_with_polling(
                 success: false,
                 message: "No data available within timeout period".to_string(),
                 count: 0,
+                ids: Vec::new(),
                 data: Vec::new(),
             })
         }
@@ -69,10 +73,7 @@ async fn poll_for_data(
                 sleep(poll_interval).await;
             }
             Err(e) => {
-                return Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    e
-                )));
+                return Err(Box::new(std::io::Error::other(e)));
             }
         }
     }
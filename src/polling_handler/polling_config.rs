@@ -35,6 +35,6 @@ impl PollingConfig {
     }
 
     pub fn get_max_polled_item(&self) -> usize {
-        self.max_polled_items.clone()
+        self.max_polled_items
     }
 }
\ No newline at end of file
@@ -0,0 +1,298 @@
+// source_ip.rs
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use actix_web::{web, Error, HttpRequest};
+use log::{error, info, warn};
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+pub mod test;
+
+/// An allowlist file is checked for changes at most this often
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An address or CIDR block, e.g. `192.30.252.0/22` or `2a0a:a440::/29`
+#[derive(Clone, Copy, PartialEq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl fmt::Debug for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+// Custom deserialization for IpRange, so malformed ranges fail at config load
+impl<'de> Deserialize<'de> for IpRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        IpRange::parse(&s).map_err(D::Error::custom)
+    }
+}
+
+impl IpRange {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid IP range: {}. Expected an address or CIDR block such as 192.0.2.0/24", source);
+
+        let (address, prefix) = match source.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (source.trim(), None),
+        };
+        let network = address.parse::<IpAddr>().map_err(|_| invalid())?.to_canonical();
+
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max_prefix);
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+
+        Ok(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the range takes in every address of its family
+    pub fn is_everything(&self) -> bool {
+        self.prefix == 0
+    }
+}
+
+/// Read one range per line, skipping blank lines and `#` comments
+pub fn parse_ranges(raw: &str) -> Result<Vec<IpRange>, String> {
+    raw.lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| IpRange::parse(line).map_err(|e| format!("{} on line {}", e, index + 1)))
+        .collect()
+}
+
+/// Ranges read from an allowlist file, shared by every worker
+#[derive(Debug, Default)]
+struct FileRanges {
+    ranges: Vec<IpRange>,
+    modified: Option<SystemTime>,
+    checked_at: Option<Instant>,
+}
+
+/// Source addresses a verification block or data alias accepts
+#[derive(Clone, Debug, Deserialize)]
+pub struct IpAllowlist {
+    #[serde(default)]
+    ranges: Vec<IpRange>,
+    #[serde(default)]
+    file: Option<String>, // One range per line, read again whenever the file changes
+    #[serde(skip)]
+    file_ranges: Arc<RwLock<FileRanges>>,
+}
+
+impl IpAllowlist {
+    pub fn get_ranges(&self) -> &[IpRange] {
+        &self.ranges
+    }
+
+    pub fn get_file(&self) -> Option<String> {
+        self.file.clone()
+    }
+
+    /// Read the ranges of `file`, if one is configured
+    pub fn load_ranges(&mut self) -> Result<(), String> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        let (ranges, modified) = read_ranges_file(path)?;
+        self.file_ranges = Arc::new(RwLock::new(FileRanges { ranges, modified, checked_at: Some(Instant::now()) }));
+        Ok(())
+    }
+
+    pub async fn allows(&self, ip: IpAddr) -> bool {
+        self.refresh_file().await;
+
+        self.ranges.iter().any(|range| range.contains(ip))
+            || self.file_ranges.read().is_ok_and(|file| file.ranges.iter().any(|range| range.contains(ip)))
+    }
+
+    /// Require a known source address inside the allowlist
+    pub async fn check(&self, ip: Option<IpAddr>, route: &str) -> Result<(), Error> {
+        match ip {
+            Some(ip) if self.allows(ip).await => Ok(()),
+            Some(ip) => {
+                error!("Source IP {} is not allowed for {}", ip, route);
+                Err(actix_web::error::ErrorForbidden("Source IP not allowed"))
+            }
+            None => {
+                error!("Source IP of a request for {} could not be determined", route);
+                Err(actix_web::error::ErrorForbidden("Source IP not allowed"))
+            }
+        }
+    }
+
+    /// Read `file` again when its modification time changed, keeping the current ranges if that fails.
+    /// The file is read on the blocking thread pool, so a slow disk does not stall the worker
+    async fn refresh_file(&self) {
+        let Some(path) = self.file.clone() else {
+            return;
+        };
+
+        let due = self.file_ranges
+            .read()
+            .is_ok_and(|file| file.checked_at.is_none_or(|at| at.elapsed() >= FILE_CHECK_INTERVAL));
+        if !due {
+            return;
+        }
+
+        let modified = match self.file_ranges.write() {
+            Ok(mut file) => {
+                file.checked_at = Some(Instant::now());
+                file.modified
+            }
+            Err(_) => return,
+        };
+
+        let file_path = path.clone();
+        let read = web::block(move || {
+            let changed = fs::metadata(&file_path).and_then(|metadata| metadata.modified()).ok() != modified;
+            changed.then(|| read_ranges_file(&file_path))
+        }).await;
+
+        match read {
+            Ok(None) => {}
+            Ok(Some(Ok((ranges, modified)))) => {
+                info!("Loaded {} IP range(s) from {}", ranges.len(), path);
+                if let Ok(mut file) = self.file_ranges.write() {
+                    file.ranges = ranges;
+                    file.modified = modified;
+                }
+            }
+            Ok(Some(Err(e))) => warn!("{}, keeping the previous ranges", e),
+            Err(e) => warn!("Failed to check IP allowlist file {}: {}", path, e),
+        }
+    }
+}
+
+fn read_ranges_file(path: &str) -> Result<(Vec<IpRange>, Option<SystemTime>), String> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let raw = fs::read_to_string(path).map_err(|e| format!("Failed to read IP allowlist file {}: {}", path, e))?;
+    let ranges = parse_ranges(&raw).map_err(|e| format!("{} in IP allowlist file {}", e, path))?;
+    Ok((ranges, modified))
+}
+
+// Define an enum for the header a trusted proxy records the client address in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProxyHeader {
+    #[default]
+    XForwardedFor, // X-Forwarded-For: client, proxy1, proxy2
+    Forwarded,     // RFC 7239: Forwarded: for=client, for=proxy1
+}
+
+// Custom deserialization for ProxyHeader
+impl<'de> Deserialize<'de> for ProxyHeader {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.to_lowercase().as_str() {
+            "x-forwarded-for" => Ok(ProxyHeader::XForwardedFor),
+            "forwarded" => Ok(ProxyHeader::Forwarded),
+            _ => Err(serde::de::Error::custom(format!(
+                "Invalid proxy header: {}. Expected 'x-forwarded-for' or 'forwarded'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Proxies in front of the server whose forwarding header is believed.
+///
+/// Without any ranges the connection's peer address is the source IP.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TrustedProxies {
+    #[serde(default)]
+    ranges: Vec<IpRange>,
+    #[serde(default)]
+    header: ProxyHeader,
+}
+
+impl TrustedProxies {
+    pub fn get_ranges(&self) -> &[IpRange] {
+        &self.ranges
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// Address of the client that sent a request.
+    ///
+    /// Starting from the peer address, each trusted proxy is replaced by the hop it forwarded
+    /// for, walking the header from right to left, so addresses a client wrote into the header
+    /// itself are never reached. `None` when a hop that has to be used is not an address.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let mut ip = req.peer_addr()?.ip().to_canonical();
+        if !self.is_trusted(ip) {
+            return Some(ip);
+        }
+
+        let mut hops = self.forwarded_hops(req).into_iter().rev();
+        while self.is_trusted(ip) {
+            match hops.next() {
+                Some(hop) => ip = hop?.to_canonical(),
+                None => break,
+            }
+        }
+        Some(ip)
+    }
+
+    /// Addresses in the forwarding header, client first, with `None` for hops that are not addresses
+    fn forwarded_hops(&self, req: &HttpRequest) -> Vec<Option<IpAddr>> {
+        let name = match self.header {
+            ProxyHeader::XForwardedFor => "x-forwarded-for",
+            ProxyHeader::Forwarded => "forwarded",
+        };
+
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| match self.header {
+                ProxyHeader::XForwardedFor => parse_node(hop),
+                ProxyHeader::Forwarded => hop
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node)),
+            })
+            .collect()
+    }
+}
+
+/// Read a forwarded node such as `192.0.2.60`, `192.0.2.60:4711` or `"[2001:db8::17]:4711"`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| node.strip_prefix('[')?.strip_suffix(']')?.parse::<IpAddr>().ok())
+}
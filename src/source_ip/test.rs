@@ -0,0 +1,125 @@
+#[allow(unused_imports)]
+use super::*;
+
+#[cfg(test)]
+mod test_source_ip {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    fn range(source: &str) -> IpRange {
+        IpRange::parse(source).unwrap()
+    }
+
+    fn ip(source: &str) -> IpAddr {
+        source.parse().unwrap()
+    }
+
+    fn proxies(config_json: serde_json::Value) -> TrustedProxies {
+        serde_json::from_value(config_json).unwrap()
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert!(range("192.30.252.0/22").contains(ip("192.30.255.7")));
+        assert!(!range("192.30.252.0/22").contains(ip("192.30.248.1")));
+        assert!(range("10.1.2.3").contains(ip("10.1.2.3")));
+        assert!(!range("10.1.2.3").contains(ip("10.1.2.4")));
+        assert!(range("2a0a:a440::/29").contains(ip("2a0a:a447::1")));
+        assert!(!range("2a0a:a440::/29").contains(ip("10.0.0.1")));
+        // IPv4-mapped addresses, as seen on dual-stack sockets, match IPv4 ranges
+        assert!(range("10.0.0.0/8").contains(ip("::ffff:10.9.8.7")));
+        assert!(range("0.0.0.0/0").contains(ip("203.0.113.9")) && range("0.0.0.0/0").is_everything());
+
+        assert!(IpRange::parse("10.0.0.0/33").is_err());
+        assert!(IpRange::parse("10.0.0/8").is_err());
+        assert!(IpRange::parse("example.com").is_err());
+        assert!(serde_json::from_value::<IpAllowlist>(json!({"ranges": ["10.0.0.0/8", "nope"]})).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_ranges_file_is_read_and_reloaded() {
+        let path = std::env::temp_dir().join(format!("pollhook-allowlist-{}.txt", std::process::id()));
+        fs::write(&path, "# GitHub hooks\n192.30.252.0/22\n\n185.199.108.0/22  # pages\n").unwrap();
+
+        let mut allowlist: IpAllowlist = serde_json::from_value(json!({"file": path.to_str().unwrap()})).unwrap();
+        allowlist.load_ranges().unwrap();
+        assert!(allowlist.allows(ip("185.199.109.1")).await);
+        assert!(!allowlist.allows(ip("10.0.0.1")).await);
+
+        // A changed file is picked up on the next check
+        fs::write(&path, "10.0.0.0/8\n").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+        allowlist.file_ranges.write().unwrap().checked_at = None;
+        assert!(allowlist.allows(ip("10.0.0.1")).await);
+        assert!(!allowlist.allows(ip("185.199.109.1")).await);
+
+        fs::write(&path, "10.0.0.0/8\nnot-an-ip\n").unwrap();
+        let error = allowlist.clone().load_ranges().unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_check_status() {
+        let allowlist: IpAllowlist = serde_json::from_value(json!({"ranges": ["192.0.2.0/24"]})).unwrap();
+
+        assert!(allowlist.check(Some(ip("192.0.2.10")), "alias github").await.is_ok());
+        let rejected = allowlist.check(Some(ip("198.51.100.1")), "alias github").await.unwrap_err();
+        assert_eq!(rejected.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        assert!(allowlist.check(None, "alias github").await.is_err());
+    }
+
+    #[test]
+    fn test_client_ip_without_trusted_proxies_is_the_peer() {
+        let req = TestRequest::default()
+            .peer_addr("203.0.113.9:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "192.0.2.10"))
+            .to_http_request();
+
+        assert_eq!(TrustedProxies::default().client_ip(&req), Some(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn test_client_ip_behind_trusted_proxies() {
+        let proxies = proxies(json!({"ranges": ["10.0.0.0/8"]}));
+
+        // The client-supplied first entry is never reached past an untrusted hop
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.2:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "192.0.2.10, 198.51.100.7, 10.0.0.1"))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&req), Some(ip("198.51.100.7")));
+
+        // An untrusted peer's header is ignored
+        let req = TestRequest::default()
+            .peer_addr("198.51.100.7:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "192.0.2.10"))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&req), Some(ip("198.51.100.7")));
+
+        // A trusted proxy that forwarded an unknown hop leaves the client unknown
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.2:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "unknown"))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&req), None);
+    }
+
+    #[test]
+    fn test_client_ip_from_forwarded_header() {
+        let proxies = proxies(json!({"ranges": ["10.0.0.0/8"], "header": "forwarded"}));
+
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.2:40000".parse().unwrap())
+            .insert_header(("Forwarded", r#"for="[2001:db8:cafe::17]:4711";proto=https, for=10.0.0.1;by=10.0.0.2"#))
+            .insert_header(("X-Forwarded-For", "192.0.2.10"))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&req), Some(ip("2001:db8:cafe::17")));
+
+        assert!(serde_json::from_value::<TrustedProxies>(json!({"header": "x-real-ip"})).is_err());
+    }
+}
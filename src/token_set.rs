@@ -0,0 +1,113 @@
+// token_set.rs
+use std::env;
+use std::fmt;
+use std::fs;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use subtle::{Choice, ConstantTimeEq};
+
+pub mod test;
+
+/// One accepted token, kept only as its SHA-256 digest
+#[derive(Clone)]
+struct AcceptedToken {
+    digest: [u8; 32],
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Tokens accepted at the same time, so a secret can be rotated without downtime.
+///
+/// In a token list, entries are separated by commas or newlines, and each may carry an
+/// RFC 3339 expiry after a `|`, e.g. `new-token,old-token|2025-07-01T00:00:00Z`.
+#[derive(Clone, Default)]
+pub struct TokenSet {
+    tokens: Vec<AcceptedToken>,
+}
+
+// Never print the tokens, not even hashed
+impl fmt::Debug for TokenSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenSet").field("tokens", &self.tokens.len()).finish()
+    }
+}
+
+impl TokenSet {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+
+        for entry in raw.split([',', '\n']).map(str::trim) {
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+
+            let (token, expires_at) = match entry.split_once('|') {
+                Some((token, expiry)) => {
+                    let expires_at = DateTime::parse_from_rfc3339(expiry.trim())
+                        .map_err(|e| format!("Invalid token expiry '{}': {}", expiry.trim(), e))?;
+                    (token.trim(), Some(expires_at.with_timezone(&Utc)))
+                }
+                None => (entry, None),
+            };
+
+            tokens.push(AcceptedToken { digest: Sha256::digest(token.as_bytes()).into(), expires_at });
+        }
+
+        if tokens.is_empty() {
+            return Err("No tokens found".to_string());
+        }
+
+        Ok(Self { tokens })
+    }
+
+    /// A single token taken as written, commas included, but without surrounding whitespace
+    pub fn literal(token: &str) -> Result<Self, String> {
+        // Secret mounts and .env files often leave a trailing newline
+        let token = token.trim();
+        if token.is_empty() {
+            return Err("No tokens found".to_string());
+        }
+
+        Ok(Self { tokens: vec![AcceptedToken { digest: Sha256::digest(token.as_bytes()).into(), expires_at: None }] })
+    }
+
+    /// Load a token list from the secrets file named by `<VAR>_FILE` or from `<VAR>_LIST`, in that
+    /// order, or else the single literal token in `VAR`
+    pub fn from_env(var: &str) -> Result<Self, String> {
+        let file_var = format!("{}_FILE", var);
+        let list_var = format!("{}_LIST", var);
+
+        if let Ok(path) = env::var(&file_var) {
+            let raw = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {} ({}): {}", file_var, path, e))?;
+            return Self::parse(&raw).map_err(|e| format!("{} in {}", e, file_var));
+        }
+
+        if let Ok(raw) = env::var(&list_var) {
+            return Self::parse(&raw).map_err(|e| format!("{} in {}", e, list_var));
+        }
+
+        let token = env::var(var).map_err(|_| format!("{} is not set", var))?;
+        Self::literal(&token).map_err(|e| format!("{} in {}", e, var))
+    }
+
+    /// Whether any of `VAR`, `<VAR>_LIST` or `<VAR>_FILE` is set
+    pub fn is_configured(var: &str) -> bool {
+        [var.to_string(), format!("{}_LIST", var), format!("{}_FILE", var)]
+            .iter()
+            .any(|name| env::var(name).is_ok())
+    }
+
+    /// Check a token, without surrounding whitespace, against every unexpired entry in constant time
+    pub fn is_valid(&self, token: &str) -> bool {
+        let digest: [u8; 32] = Sha256::digest(token.trim().as_bytes()).into();
+        let now = Utc::now();
+
+        // Every entry is compared, so timing does not reveal which one matched
+        let matched = self.tokens.iter().fold(Choice::from(0), |matched, accepted| {
+            let active = Choice::from(accepted.expires_at.is_none_or(|expiry| now < expiry) as u8);
+            matched | (accepted.digest.ct_eq(&digest) & active)
+        });
+
+        matched.into()
+    }
+}
@@ -0,0 +1,81 @@
+#[allow(unused_imports)]
+use super::*;
+
+#[cfg(test)]
+mod test_token_set {
+    use super::*;
+
+    #[test]
+    fn test_rotation_accepts_every_token() {
+        let tokens = TokenSet::parse("new-token, old-token").unwrap();
+
+        assert!(tokens.is_valid("new-token"));
+        assert!(tokens.is_valid("old-token"));
+        assert!(tokens.is_valid(" old-token\n"));
+        assert!(!tokens.is_valid("other-token"));
+        assert!(!tokens.is_valid(""));
+        assert!(!tokens.is_valid("new-token, old-token"));
+    }
+
+    #[test]
+    fn test_expired_tokens_are_rejected() {
+        let tokens = TokenSet::parse("current,retired|2020-01-01T00:00:00Z,grace|2999-01-01T00:00:00+02:00").unwrap();
+
+        assert!(tokens.is_valid("current"));
+        assert!(!tokens.is_valid("retired"));
+        assert!(tokens.is_valid("grace"));
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(TokenSet::parse("").is_err());
+        assert!(TokenSet::parse(" , \n").is_err());
+
+        let err = TokenSet::parse("token|next tuesday").unwrap_err();
+        assert!(err.contains("Invalid token expiry"), "{}", err);
+    }
+
+    #[test]
+    fn test_single_token_is_literal() {
+        unsafe {
+            std::env::set_var("TEST_TOKEN_SET_LITERAL", " a,b|2020-01-01T00:00:00Z ");
+        }
+        let tokens = TokenSet::from_env("TEST_TOKEN_SET_LITERAL").unwrap();
+        assert!(tokens.is_valid("a,b|2020-01-01T00:00:00Z"));
+        assert!(!tokens.is_valid("a") && !tokens.is_valid("b"));
+
+        // A trailing newline from a secret mount is not part of the token
+        assert!(TokenSet::literal("secret\n").unwrap().is_valid("secret"));
+        assert!(TokenSet::literal("").is_err());
+        assert!(TokenSet::literal(" \n").is_err());
+    }
+
+    #[test]
+    fn test_load_from_env_or_secrets_file() {
+        unsafe {
+            std::env::set_var("TEST_TOKEN_SET_ENV", "single");
+            std::env::set_var("TEST_TOKEN_SET_ENV_LIST", "a,b");
+        }
+        // The list takes precedence over the single token
+        let tokens = TokenSet::from_env("TEST_TOKEN_SET_ENV").unwrap();
+        assert!(tokens.is_valid("a") && tokens.is_valid("b"));
+        assert!(!tokens.is_valid("single"));
+        assert!(TokenSet::is_configured("TEST_TOKEN_SET_ENV"));
+
+        // The secrets file takes precedence, one token per line with comments allowed
+        let path = std::env::temp_dir().join("pollhook_test_token_set_secrets");
+        std::fs::write(&path, "# rotated 2025-06\nfile-new\nfile-old|2999-01-01T00:00:00Z\n").unwrap();
+        unsafe {
+            std::env::set_var("TEST_TOKEN_SET_ENV_FILE", &path);
+        }
+        let tokens = TokenSet::from_env("TEST_TOKEN_SET_ENV").unwrap();
+        assert!(tokens.is_valid("file-new") && tokens.is_valid("file-old"));
+        assert!(!tokens.is_valid("a"));
+        assert!(!tokens.is_valid("# rotated 2025-06"));
+        std::fs::remove_file(&path).unwrap();
+
+        let err = TokenSet::from_env("TEST_TOKEN_SET_MISSING").unwrap_err();
+        assert_eq!(err, "TEST_TOKEN_SET_MISSING is not set");
+        assert!(!TokenSet::is_configured("TEST_TOKEN_SET_MISSING"));
+    }
+}
@@ -1,4 +1,5 @@
 pub mod extractors;
+pub mod template;
 pub mod test;
 pub mod verification_config;
 pub mod verification;
\ No newline at end of file
@@ -1,11 +1,31 @@
 // extractors.rs
-use crate::verification_config::{ChallengeConfig,TokenConfig};
-use actix_web::{web, HttpRequest, Error};
+use crate::body_format;
+use crate::verification_config::{CapturePattern, ChallengeConfig, TokenConfig};
+use actix_web::{web, HttpMessage, HttpRequest, Error};
 use bytes::Bytes;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use log::error;
 
+/// Request locations `extract_value` can read from
+pub const LOCATIONS: &[&str] = &["query", "header", "path", "param", "body", "form", "multipart", "xml"];
+
+/// Named `{param}` segments captured by a verification or data path pattern, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct PathParams(pub BTreeMap<String, String>);
+
+/// Named path segments of a request: those captured by the matched path pattern, else the route's own
+pub fn path_params(req: &HttpRequest) -> BTreeMap<String, String> {
+    if let Some(params) = req.extensions().get::<PathParams>() {
+        return params.0.clone();
+    }
+
+    req.match_info()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
 /// Generic function to extract a value from a request based on the location and path
 pub fn extract_value(
     req: &HttpRequest,
@@ -23,7 +43,7 @@ pub fn extract_value(
                 Ok(value.clone())
             } else {
                 error!("{} not found in query parameter: {}", value_type, locate_path);
-                Err(Error::from(actix_web::error::ErrorBadRequest(format!("{} not found in query", value_type))))
+                Err(actix_web::error::ErrorBadRequest(format!("{} not found in query", value_type)))
             }
         },
         "header" => {
@@ -33,11 +53,11 @@ pub fn extract_value(
                     Ok(value.to_string())
                 } else {
                     error!("Invalid header value for: {}", locate_path);
-                    Err(Error::from(actix_web::error::ErrorBadRequest("Invalid header value")))
+                    Err(actix_web::error::ErrorBadRequest("Invalid header value"))
                 }
             } else {
                 error!("{} not found in header: {}", value_type, locate_path);
-                Err(Error::from(actix_web::error::ErrorBadRequest(format!("{} not found in header", value_type))))
+                Err(actix_web::error::ErrorBadRequest(format!("{} not found in header", value_type)))
             }
         },
         "path" => {
@@ -48,59 +68,149 @@ pub fn extract_value(
                     Ok(path_segments[index].to_string())
                 } else {
                     error!("Path segment index out of bounds: {}", index);
-                    Err(Error::from(actix_web::error::ErrorBadRequest("Path segment index out of bounds")))
+                    Err(actix_web::error::ErrorBadRequest("Path segment index out of bounds"))
                 }
             } else {
                 error!("Invalid path segment index: {}", locate_path);
-                Err(Error::from(actix_web::error::ErrorBadRequest("Invalid path segment index")))
+                Err(actix_web::error::ErrorBadRequest("Invalid path segment index"))
+            }
+        },
+        "param" => {
+            // Extract from a named path segment, e.g. {tenant}
+            if let Some(value) = path_params(req).remove(locate_path) {
+                Ok(value)
+            } else {
+                error!("{} not found in path parameter: {}", value_type, locate_path);
+                Err(actix_web::error::ErrorBadRequest(format!("{} not found in path parameters", value_type)))
             }
         },
-        "body" => {
-            // Extract from request body
+        "body" | "form" | "multipart" | "xml" => {
+            // Extract from request body, parsed by the location or else by its Content-Type
             if let Some(body_bytes) = body {
-                let body_str = String::from_utf8_lossy(body_bytes);
-                if let Ok(json) = serde_json::from_str::<Value>(&body_str) {
-                    let path_parts: Vec<&str> = locate_path.split("::").collect();
-                    let mut current_value = &json;
-
-                    for part in path_parts {
-                        if let Some(next_value) = current_value.get(part) {
-                            current_value = next_value;
-                        } else {
-                            error!("{} path not found in body: {}", value_type, locate_path);
-                            return Err(Error::from(actix_web::error::ErrorBadRequest(format!("{} path not found in body", value_type))));
-                        }
+                let parsed = match location {
+                    "form" => body_format::form_to_json(body_bytes).map_err(|e| e.to_string()),
+                    "multipart" => body_format::multipart_to_json(req, body_bytes),
+                    "xml" => body_format::xml_to_json(body_bytes),
+                    _ => body_format::parse_body(req, body_bytes),
+                };
+                match parsed {
+                    Ok(json) => extract_from_json(&json, locate_path, value_type),
+                    Err(e) => {
+                        error!("Failed to parse {} body: {}", location, e);
+                        Err(actix_web::error::ErrorBadRequest(format!("Failed to parse {} body", location)))
                     }
-
-                    if let Some(value) = current_value.as_str() {
-                        Ok(value.to_string())
-                    } else {
-                        error!("{} value in body is not a string", value_type);
-                        Err(Error::from(actix_web::error::ErrorBadRequest(format!("{} value in body is not a string", value_type))))
-                    }
-                } else {
-                    error!("Failed to parse body as JSON");
-                    Err(Error::from(actix_web::error::ErrorBadRequest("Failed to parse body as JSON")))
                 }
             } else {
                 error!("Body expected but not provided");
-                Err(Error::from(actix_web::error::ErrorBadRequest("Body expected but not provided")))
+                Err(actix_web::error::ErrorBadRequest("Body expected but not provided"))
             }
         },
         _ => {
             error!("Unsupported {} location: {}", value_type, location);
-            Err(Error::from(actix_web::error::ErrorBadRequest(format!("Unsupported {} location", value_type))))
+            Err(actix_web::error::ErrorBadRequest(format!("Unsupported {} location", value_type)))
         }
     }
 }
 
+/// Read a scalar out of a JSON document and render it as a string
+pub fn extract_from_json(json: &Value, locate_path: &str, value_type: &str) -> Result<String, Error> {
+    let Some(value) = lookup_json(json, locate_path) else {
+        error!("{} path not found in body: {}", value_type, locate_path);
+        return Err(actix_web::error::ErrorBadRequest(format!("{} path not found in body", value_type)));
+    };
+
+    json_scalar_to_string(value).ok_or_else(|| {
+        error!("{} value in body is not a string, number or boolean", value_type);
+        actix_web::error::ErrorBadRequest(format!("{} value in body is not a scalar", value_type))
+    })
+}
+
+/// Find a value in a JSON document.
+///
+/// Three path syntaxes are accepted:
+/// - RFC 6901 JSON Pointer when the path starts with `/`, e.g. `/entry/0/id`
+/// - a JSONPath subset when it starts with `$`, e.g. `$.entry[0].id` or `$['entry'][0]['id']`
+/// - `::`-separated keys otherwise, e.g. `entry::0::id`, where numeric keys also index arrays
+pub fn lookup_json<'a>(json: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.starts_with('/') {
+        return json.pointer(path);
+    }
+
+    let segments = if path.starts_with('$') {
+        parse_json_path(path)?
+    } else {
+        path.split("::").map(|s| s.to_string()).collect()
+    };
+
+    segments.iter().try_fold(json, |current, segment| match current {
+        Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => current.get(segment.as_str()),
+    })
+}
+
+/// Split a JSONPath subset (`$`, `.key`, `['key']`, `["key"]`, `[index]`) into segments
+fn parse_json_path(path: &str) -> Option<Vec<String>> {
+    let mut segments = Vec::new();
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+
+    while !rest.is_empty() {
+        if let Some(bracketed) = rest.strip_prefix('[') {
+            let end = bracketed.find(']')?;
+            let inner = bracketed[..end].trim();
+            let key = inner
+                .strip_prefix('\'').and_then(|k| k.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|k| k.strip_suffix('"')))
+                .unwrap_or(inner);
+            segments.push(key.to_string());
+            rest = &bracketed[end + 1..];
+        } else {
+            let dotted = rest.strip_prefix('.').unwrap_or(rest);
+            let end = dotted.find(['.', '[']).unwrap_or(dotted.len());
+            if end == 0 {
+                return None;
+            }
+            segments.push(dotted[..end].to_string());
+            rest = &dotted[end..];
+        }
+    }
+
+    Some(segments)
+}
+
+/// Render strings, numbers and booleans the same way they appear in JSON (strings unquoted)
+pub fn json_scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+/// Narrow an extracted value down to the capture of its configured pattern, if any
+pub fn apply_pattern(
+    value: String,
+    pattern: Option<&CapturePattern>,
+    value_type: &str
+) -> Result<String, Error> {
+    let Some(pattern) = pattern else {
+        return Ok(value);
+    };
+
+    pattern.capture(&value).ok_or_else(|| {
+        error!("{} value does not match pattern: {}", value_type, pattern.as_str());
+        actix_web::error::ErrorBadRequest(format!("{} value does not match pattern", value_type))
+    })
+}
+
 // Re-export the specific functions with proper type imports
 pub fn extract_token(
     req: &HttpRequest,
     token_config: &TokenConfig,
     body: &Option<Bytes>
 ) -> Result<String, Error> {
-    extract_value(req, &token_config.get_in(), &token_config.get_locate(), body, "Token")
+    let value = extract_value(req, &token_config.get_in(), &token_config.get_locate(), body, "Token")?;
+    apply_pattern(value, token_config.get_pattern(), "Token")
 }
 
 pub fn extract_challenge(
@@ -108,6 +218,7 @@ pub fn extract_challenge(
     challenge_config: &ChallengeConfig,
     body: &Option<Bytes>
 ) -> Result<String, Error> {
-    extract_value(req, &challenge_config.get_in(), &challenge_config.get_locate(), body, "Challenge")
+    let value = extract_value(req, &challenge_config.get_in(), &challenge_config.get_locate(), body, "Challenge")?;
+    apply_pattern(value, challenge_config.get_pattern(), "Challenge")
 }
 
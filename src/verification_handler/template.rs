@@ -0,0 +1,347 @@
+// template.rs
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use actix_web::{web, HttpRequest};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
+use regex::Regex;
+use serde_json::Value;
+use crate::body_format;
+use crate::data_handler::signature::hmac_digest;
+use crate::data_handler::signature_config::SignatureAlgorithm;
+use crate::verification_handler::extractors::{json_scalar_to_string, lookup_json, path_params};
+
+/// Placeholders that take a key after a dot, e.g. `@query.hub.challenge`
+const KEYED_PLACEHOLDERS: &[&str] = &["query", "header", "body", "path", "param"];
+
+/// Placeholder replaced by the extracted challenge, `@challenge`
+const CHALLENGE_PLACEHOLDER: &str = "challenge";
+
+// Define an enum for how substituted values are escaped in the rendered text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    None,
+    Json, // Inside a JSON string literal
+    Xml,  // Inside XML or HTML text or attribute values
+}
+
+impl Escape {
+    /// Escaping that matches a response MIME type
+    pub fn for_content_type(content_type: &str) -> Self {
+        let content_type = content_type.to_lowercase();
+        if content_type.contains("json") {
+            Escape::Json
+        } else if content_type.contains("xml") || content_type.contains("html") {
+            Escape::Xml
+        } else {
+            Escape::None
+        }
+    }
+
+    fn apply(&self, value: &str) -> String {
+        match self {
+            Escape::None => value.to_string(),
+            Escape::Json => {
+                let quoted = Value::String(value.to_string()).to_string();
+                quoted[1..quoted.len() - 1].to_string()
+            }
+            Escape::Xml => value
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+                .replace('\'', "&apos;"),
+        }
+    }
+}
+
+/// Values a response template can refer to
+pub struct TemplateContext<'a> {
+    challenge: &'a str,
+    request: Option<&'a HttpRequest>,
+    body: Option<&'a Bytes>,
+    parsed_body: OnceCell<Option<Value>>, // Parsed on first `@body.` lookup
+    secrets: Option<&'a HashMap<String, String>>, // `@hmac_*` secrets by env var, read at startup
+}
+
+impl<'a> TemplateContext<'a> {
+    pub fn new(challenge: &'a str) -> Self {
+        Self { challenge, request: None, body: None, parsed_body: OnceCell::new(), secrets: None }
+    }
+
+    /// Make `@hmac_*` functions sign with these secrets, keyed by the env var they were read from
+    pub fn with_secrets(mut self, secrets: &'a HashMap<String, String>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// Make `@query.`, `@header.`, `@body.`, `@path.` and `@param.` placeholders read from this request
+    pub fn with_request(mut self, req: &'a HttpRequest, body: Option<&'a Bytes>) -> Self {
+        self.request = Some(req);
+        self.body = body;
+        self
+    }
+
+    fn parsed_body(&self) -> Option<&Value> {
+        self.parsed_body
+            .get_or_init(|| {
+                let (req, body) = (self.request?, self.body?);
+                body_format::parse_body(req, body).ok()
+            })
+            .as_ref()
+    }
+
+    /// Value of a placeholder, or `None` when the name is not a placeholder
+    fn lookup(&self, name: &str, key: Option<&str>) -> Option<Value> {
+        let value = match (name, key) {
+            (CHALLENGE_PLACEHOLDER, None) => Value::String(self.challenge.to_string()),
+            ("now", None) => Value::String(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            ("uuid", None) => Value::String(uuid::Uuid::new_v4().to_string()),
+            ("query", Some(key)) => self.request
+                .and_then(|req| web::Query::<HashMap<String, String>>::from_query(req.query_string()).ok())
+                .and_then(|query| query.get(key).cloned())
+                .map(Value::String)
+                .unwrap_or(Value::Null),
+            ("header", Some(key)) => self.request
+                .and_then(|req| req.headers().get(key)?.to_str().ok().map(str::to_string))
+                .map(Value::String)
+                .unwrap_or(Value::Null),
+            ("path", Some(key)) => self.request
+                .zip(key.parse::<usize>().ok())
+                .and_then(|(req, index)| req.path().split('/').nth(index).map(str::to_string))
+                .map(Value::String)
+                .unwrap_or(Value::Null),
+            ("param", Some(key)) => self.request
+                .and_then(|req| path_params(req).remove(key))
+                .map(Value::String)
+                .unwrap_or(Value::Null),
+            ("body", Some(key)) => self.parsed_body()
+                .and_then(|json| lookup_json(json, key))
+                .cloned()
+                .unwrap_or(Value::Null),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Text of a value as it is substituted into a template: missing values render empty
+fn value_to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Array(_) | Value::Object(_) => value.to_string(),
+        _ => json_scalar_to_string(value).unwrap_or_default(),
+    }
+}
+
+/// Render a response template without escaping.
+///
+/// `@challenge` is replaced by the extracted challenge, also when letters follow it, and functions
+/// such as `@hmac_sha256_hex(SECRET_ENV, @challenge)`, `@base64(...)` and `@hex(...)` compute
+/// derived tokens. Text after `@` that is not a known placeholder is kept as is.
+pub fn render_template(template: &str, context: &TemplateContext) -> Result<String, String> {
+    render_escaped(template, context, Escape::None)
+}
+
+/// Render a template, escaping each substituted value (but not the template's own text)
+pub fn render_escaped(template: &str, context: &TemplateContext, escape: Escape) -> Result<String, String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(at) = rest.find('@') {
+        output.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+
+        match render_expression(after, context)? {
+            Some((value, consumed)) => {
+                output.push_str(&escape.apply(&value_to_text(&value)));
+                rest = &after[consumed..];
+            }
+            None => {
+                output.push('@');
+                rest = after;
+            }
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Render a template that is exactly one expression to its typed value, e.g. a number
+/// from `@body./count`; any other template renders to a string
+pub fn render_value(template: &str, context: &TemplateContext) -> Result<Value, String> {
+    if let Some(expression) = template.strip_prefix('@')
+        && let Some((value, consumed)) = render_expression(expression, context)?
+        && consumed == expression.len()
+    {
+        return Ok(value);
+    }
+
+    render_template(template, context).map(Value::String)
+}
+
+/// Render every string in structured data, keeping the type of single-expression leaves
+pub fn render_structured(data: &Value, context: &TemplateContext) -> Result<Value, String> {
+    Ok(match data {
+        Value::String(template) => render_value(template, context)?,
+        Value::Array(items) => Value::Array(
+            items.iter().map(|item| render_structured(item, context)).collect::<Result<_, _>>()?
+        ),
+        Value::Object(fields) => {
+            let mut rendered = serde_json::Map::new();
+            for (key, value) in fields {
+                rendered.insert(render_template(key, context)?, render_structured(value, context)?);
+            }
+            Value::Object(rendered)
+        }
+        other => other.clone(),
+    })
+}
+
+/// Render the expression right after an `@`, returning its value and how many bytes it spans
+fn render_expression(input: &str, context: &TemplateContext) -> Result<Option<(Value, usize)>, String> {
+    // `@{...}` delimits an expression explicitly, e.g. `@{query.hub.challenge}.`
+    if let Some(braced) = input.strip_prefix('{') {
+        let end = braced.find('}').ok_or("Unclosed @{ in response template")?;
+        let inner = &braced[..end];
+        // Inside braces a key may use any character but `}`
+        if let Some((name, key)) = inner.split_once('.')
+            && KEYED_PLACEHOLDERS.contains(&name)
+            && let Some(value) = context.lookup(name, Some(key))
+        {
+            return Ok(Some((value, end + 2)));
+        }
+        return match render_expression(inner, context)? {
+            Some((value, consumed)) if consumed == inner.len() => Ok(Some((value, end + 2))),
+            _ => Err(format!("Unknown placeholder in response template: @{{{}}}", inner)),
+        };
+    }
+
+    let name_len = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(input.len());
+    let name = &input[..name_len];
+
+    if name.is_empty() {
+        return Ok(None);
+    }
+
+    // `@challenge` has always been replaced wherever it appears, even right before letters
+    if name.starts_with(CHALLENGE_PLACEHOLDER) {
+        return Ok(context.lookup(CHALLENGE_PLACEHOLDER, None).map(|value| (value, CHALLENGE_PLACEHOLDER.len())));
+    }
+
+    if let Some(arguments) = input[name_len..].strip_prefix('(') {
+        let (args, consumed) = split_arguments(arguments)?;
+        let value = call_function(name, &args, context)?;
+        return Ok(Some((value, name_len + 1 + consumed)));
+    }
+
+    if KEYED_PLACEHOLDERS.contains(&name)
+        && let Some(rest) = input[name_len..].strip_prefix('.')
+    {
+        let key_len = key_length(rest);
+        if key_len > 0 {
+            let value = context.lookup(name, Some(&rest[..key_len]));
+            return Ok(value.map(|value| (value, name_len + 1 + key_len)));
+        }
+    }
+
+    Ok(context.lookup(name, None).map(|value| (value, name_len)))
+}
+
+/// Length of a placeholder key, e.g. `hub.challenge`, `entry::0` or `$.entry[0].id`.
+/// A `]` only belongs to the key when it closes a `[`, and a `:` only as part of `::`,
+/// so `[@query.x]` and `@param.a:@param.b` keep their punctuation
+fn key_length(input: &str) -> usize {
+    let mut depth = 0;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            ':' if input[i + 1..].starts_with(':') => {
+                chars.next();
+            }
+            c if c.is_ascii_alphanumeric() || "_-./$~".contains(c) => {}
+            _ => return i,
+        }
+    }
+
+    input.len()
+}
+
+/// Split a function's arguments on top-level commas, up to its closing parenthesis
+fn split_arguments(input: &str) -> Result<(Vec<&str>, usize), String> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                args.push(input[start..i].trim());
+                return Ok((args, i + 1));
+            }
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    Err("Unclosed function call in response template".to_string())
+}
+
+fn call_function(name: &str, args: &[&str], context: &TemplateContext) -> Result<Value, String> {
+    match (name, args) {
+        ("base64", [value]) => Ok(Value::String(BASE64.encode(render_template(value, context)?))),
+        ("hex", [value]) => Ok(Value::String(hex::encode(render_template(value, context)?))),
+        // Parse the rendered text as JSON, so `@json(@query.count)` becomes a number
+        ("json", [value]) => {
+            let text = render_template(value, context)?;
+            serde_json::from_str(&text).map_err(|e| format!("@json() argument is not valid JSON ({}): {}", e, text))
+        }
+        (_, [secret_env, value]) if name.starts_with("hmac_") => {
+            let (algorithm, encoding) = parse_hmac_function(name)?;
+            let secret = context.secrets
+                .and_then(|secrets| secrets.get(*secret_env))
+                .ok_or_else(|| format!("{} is not loaded (used by @{} in response template)", secret_env, name))?;
+            let digest = hmac_digest(algorithm, secret.as_bytes(), render_template(value, context)?.as_bytes());
+
+            Ok(Value::String(match encoding {
+                "hex" => hex::encode(digest),
+                _ => BASE64.encode(digest),
+            }))
+        }
+        _ => Err(format!("Unknown template function or wrong argument count: @{}", name)),
+    }
+}
+
+/// Env vars named as secrets by the `@hmac_*` functions of a template, so they can be read at startup
+pub fn secret_envs(template: &str) -> Vec<String> {
+    let hmac_call = Regex::new(r"@\{?hmac_\w+\(\s*([^,()\s]+)\s*,").expect("valid regex");
+    hmac_call.captures_iter(template).map(|captures| captures[1].to_string()).collect()
+}
+
+/// Read `hmac_<algorithm>_<encoding>`, e.g. `hmac_sha256_hex`
+fn parse_hmac_function(name: &str) -> Result<(SignatureAlgorithm, &str), String> {
+    let mut parts = name.splitn(3, '_').skip(1);
+    let algorithm = match parts.next() {
+        Some("sha1") => SignatureAlgorithm::HmacSha1,
+        Some("sha256") => SignatureAlgorithm::HmacSha256,
+        Some("sha512") => SignatureAlgorithm::HmacSha512,
+        _ => return Err(format!("Unknown HMAC algorithm in template function: @{}", name)),
+    };
+
+    match parts.next() {
+        Some(encoding @ ("hex" | "base64")) => Ok((algorithm, encoding)),
+        _ => Err(format!("Unknown HMAC encoding in template function: @{}", name)),
+    }
+}
@@ -274,8 +274,706 @@ mod test_verification {
     fn test_set_token() {
         let mut config = create_test_config(VERIFICATION_PATH, "GET");
 
-        config.set_expected_token("new_token".to_string());
-        assert_eq!(config.get_expected_token(), "new_token");
+        config.set_expected_tokens(crate::token_set::TokenSet::parse("new_token").unwrap());
+        assert!(config.is_token_valid("new_token".to_string()));
+        assert!(!config.is_token_valid("old_token".to_string()));
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_verification_map {
+    use serde_json::json;
+    use crate::verification_handler::verification_config::{VerificationMap, DEFAULT_TOKEN_ENV, DEFAULT_VERIFICATION_NAME};
+
+    fn block(path: &str, token_env: Option<&str>) -> serde_json::Value {
+        let mut block = json!({
+            "path": path,
+            "token": { "in": "query", "locate": "hub.verify_token" },
+            "challenge": { "in": "query", "locate": "hub.challenge" },
+            "response": { "type": "text/plain", "data": "@challenge" }
+        });
+        if let Some(env) = token_env {
+            block["token_env"] = json!(env);
+        }
+        block
+    }
+
+    #[test]
+    fn test_legacy_single_block() {
+        let map: VerificationMap = serde_json::from_value(block("/callhook/meta", None)).unwrap();
+
+        assert_eq!(map.get_yaml_location(DEFAULT_VERIFICATION_NAME), "verification");
+        assert_eq!(map.get_name_method_config_vec().len(), 1);
+        let config = map.get(DEFAULT_VERIFICATION_NAME).unwrap();
+        assert_eq!(config.get_verification_path(), "/callhook/meta");
+        assert_eq!(config.get_token_env(), DEFAULT_TOKEN_ENV);
+    }
+
+    #[test]
+    fn test_named_blocks() {
+        let map: VerificationMap = serde_json::from_value(json!({
+            "meta": block("/callhook/meta", Some("META_VERIFY_TOKEN")),
+            "slack": block("/callhook/slack", Some("SLACK_VERIFY_TOKEN")),
+        })).unwrap();
+
+        assert_eq!(map.get_name_method_config_vec().len(), 2);
+        assert_eq!(map.get_yaml_location("meta"), "verification.meta");
+        assert_eq!(map.get("meta").unwrap().get_token_env(), "META_VERIFY_TOKEN");
+        assert_eq!(map.get("slack").unwrap().get_verification_path(), "/callhook/slack");
+        assert!(map.get(DEFAULT_VERIFICATION_NAME).is_none());
+
+        // Routes are registered in name order
+        let names: Vec<String> = map.get_name_method_config_vec().into_iter().map(|t| t.0).collect();
+        assert_eq!(names, vec!["meta", "slack"]);
+    }
+
+    #[test]
+    fn test_block_named_path_is_not_the_legacy_layout() {
+        let map: VerificationMap = serde_json::from_value(json!({
+            "path": block("/callhook/path", None),
+            "meta": block("/callhook/meta", None),
+        })).unwrap();
+
+        assert_eq!(map.get("path").unwrap().get_verification_path(), "/callhook/path");
+        assert_eq!(map.get_yaml_location("path"), "verification.path");
+        assert!(map.get(DEFAULT_VERIFICATION_NAME).is_none());
+    }
+
+    #[test]
+    fn test_blocks_match_their_own_paths() {
+        let map: VerificationMap = serde_json::from_value(json!({
+            "meta": block("/callhook/meta", None),
+            "github": block("/callhook/github/...", None),
+        })).unwrap();
+
+        let meta = map.get("meta").unwrap();
+        let github = map.get("github").unwrap();
+
+        assert!(meta.is_verification_path("/callhook/meta".to_string()));
+        assert!(!github.is_verification_path("/callhook/meta".to_string()));
+        assert!(github.is_verification_path("/callhook/github/app".to_string()));
+        assert!(!meta.is_verification_path("/callhook/github/app".to_string()));
+    }
+
+    #[test]
+    fn test_load_expected_tokens() {
+        let mut map: VerificationMap = serde_json::from_value(json!({
+            "meta": block("/callhook/meta", Some("TEST_MAP_META_TOKEN")),
+            "slack": block("/callhook/slack", Some("TEST_MAP_SLACK_TOKEN")),
+        })).unwrap();
+
+        // Missing env var is reported with the block name
+        let err = map.load_expected_tokens().unwrap_err();
+        assert!(err.contains("TEST_MAP_"));
+
+        unsafe {
+            std::env::set_var("TEST_MAP_META_TOKEN", "meta-secret");
+            std::env::set_var("TEST_MAP_SLACK_TOKEN", "slack-secret");
+        }
+        map.load_expected_tokens().unwrap();
+
+        assert!(map.get("meta").unwrap().is_token_valid("meta-secret".to_string()));
+        assert!(map.get("slack").unwrap().is_token_valid("slack-secret".to_string()));
+        assert!(!map.get("meta").unwrap().is_token_valid("slack-secret".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test_template {
+    use super::template::*;
+    use super::verification::build_response;
+    use crate::verification_handler::verification_config::ResponseConfig;
+
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use bytes::Bytes;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    const CHALLENGE: &str = "The quick brown fox jumps over the lazy dog";
+
+    fn render(template: &str) -> Result<String, String> {
+        render_template(template, &TemplateContext::new(CHALLENGE))
+    }
+
+    #[test]
+    fn test_plain_placeholders() {
+        assert_eq!(render("@challenge").unwrap(), CHALLENGE);
+        assert_eq!(render("value=@challenge;").unwrap(), format!("value={};", CHALLENGE));
+        assert_eq!(render("@challengeXYZ @challenge(1)").unwrap(), format!("{}XYZ {}(1)", CHALLENGE, CHALLENGE));
+
+        // Unknown placeholders and stray @ are kept as is
+        assert_eq!(render("mail@example.com @ @other").unwrap(), "mail@example.com @ @other");
+    }
+
+    #[test]
+    fn test_hmac_functions() {
+        let secrets = HashMap::from([("TEST_TEMPLATE_SECRET".to_string(), "key".to_string())]);
+        let sign = |template: &str| render_template(template, &TemplateContext::new(CHALLENGE).with_secrets(&secrets));
+
+        // Zoom endpoint.url_validation
+        assert_eq!(
+            sign("@hmac_sha256_hex(TEST_TEMPLATE_SECRET, @challenge)").unwrap(),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+
+        // Twitter/X CRC
+        assert_eq!(
+            sign("sha256=@hmac_sha256_base64(TEST_TEMPLATE_SECRET, @challenge)").unwrap(),
+            "sha256=97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg="
+        );
+
+        assert_eq!(
+            sign("@hmac_sha1_base64(TEST_TEMPLATE_SECRET, @challenge)").unwrap(),
+            "3nybhbi3iqa8ino29wqQcBydtNk="
+        );
+
+        // Secrets that were not loaded at startup
+        assert!(sign("@hmac_sha256_hex(TEST_TEMPLATE_MISSING_SECRET, @challenge)").is_err());
+        assert!(render("@hmac_sha256_hex(TEST_TEMPLATE_SECRET, @challenge)").is_err());
+        assert!(sign("@hmac_md5_hex(TEST_TEMPLATE_SECRET, @challenge)").is_err());
+    }
+
+    #[test]
+    fn test_encoders_and_nesting() {
+        assert_eq!(render("@base64(abc)").unwrap(), "YWJj");
+        assert_eq!(render("@hex(abc)").unwrap(), "616263");
+        assert_eq!(render("@hex(@base64(abc))").unwrap(), "59574a6a");
+        assert_eq!(render("@base64(x-@challenge)").unwrap(), "eC1UaGUgcXVpY2sgYnJvd24gZm94IGp1bXBzIG92ZXIgdGhlIGxhenkgZG9n");
+
+        assert!(render("@base64(abc").is_err());
+        assert!(render("@base64(a, b)").is_err());
+        assert!(render("@unknown(abc)").is_err());
+    }
+
+    #[test]
+    fn test_secret_envs() {
+        assert_eq!(
+            secret_envs("@hmac_sha256_hex(ZOOM_SECRET, @challenge) @{hmac_sha1_base64( OTHER ,x)} @base64(SECRET)"),
+            vec!["ZOOM_SECRET", "OTHER"]
+        );
+
+        let mut response_config: ResponseConfig = serde_json::from_value(json!({
+            "type": "application/json",
+            "data": { "token": "@hmac_sha256_hex(TEST_TEMPLATE_UNSET_SECRET, @challenge)" }
+        })).unwrap();
+        assert_eq!(response_config.secret_envs(), vec!["TEST_TEMPLATE_UNSET_SECRET"]);
+        assert!(response_config.load_secrets().unwrap_err().contains("TEST_TEMPLATE_UNSET_SECRET"));
+    }
+
+    #[actix_web::test]
+    async fn test_zoom_url_validation_response() {
+        unsafe { std::env::set_var("TEST_ZOOM_SECRET", "key") };
+        let mut response_config: ResponseConfig = serde_json::from_value(json!({
+            "type": "application/json",
+            "data": r#"{"plainToken":"@challenge","encryptedToken":"@hmac_sha256_hex(TEST_ZOOM_SECRET, @challenge)"}"#
+        })).unwrap();
+        response_config.load_secrets().unwrap();
+
+        let response = build_response(&response_config, TemplateContext::new(CHALLENGE), StatusCode::OK).unwrap();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, Bytes::from(format!(
+            r#"{{"plainToken":"{}","encryptedToken":"f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"}}"#,
+            CHALLENGE
+        )));
+    }
+}
+
+#[cfg(test)]
+mod test_json_lookup {
+    use super::extractors::*;
+
+    use actix_web::test;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    fn body() -> serde_json::Value {
+        json!({
+            "entry": [
+                { "id": "578564948682799", "time": 1746313253959u64, "changes": [{ "field": "feed" }] }
+            ],
+            "challenge": 42,
+            "verified": true,
+            "nothing": null,
+            "a/b": { "c.d": "escaped" },
+            "tags[0]": { "id": "literal" }
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_json_pointer() {
+        let json = body();
+        assert_eq!(lookup_json(&json, "/entry/0/id"), Some(&json!("578564948682799")));
+        assert_eq!(lookup_json(&json, "/entry/0/changes/0/field"), Some(&json!("feed")));
+        assert_eq!(lookup_json(&json, "/a~1b/c.d"), Some(&json!("escaped")));
+        assert_eq!(lookup_json(&json, "/entry/1/id"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_json_path_subset() {
+        let json = body();
+        assert_eq!(lookup_json(&json, "$.entry[0].id"), Some(&json!("578564948682799")));
+        assert_eq!(lookup_json(&json, "$.entry[0].changes[0].field"), Some(&json!("feed")));
+        assert_eq!(lookup_json(&json, "$['a/b'][\"c.d\"]"), Some(&json!("escaped")));
+        assert_eq!(lookup_json(&json, "$.challenge"), Some(&json!(42)));
+        assert_eq!(lookup_json(&json, "$.entry[3]"), None);
+        assert_eq!(lookup_json(&json, "$.entry[0"), None);
+        assert_eq!(lookup_json(&json, "$..entry"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_separator_with_array_index() {
+        let json = body();
+        assert_eq!(lookup_json(&json, "entry::0::id"), Some(&json!("578564948682799")));
+        assert_eq!(lookup_json(&json, "entry::first::id"), None);
+
+        // Brackets are part of the key unless the path starts with `$`
+        assert_eq!(lookup_json(&json, "tags[0]::id"), Some(&json!("literal")));
+        assert_eq!(lookup_json(&json, "entry[0]::id"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_scalar_values_are_stringified() {
+        let body_bytes = Some(Bytes::from(body().to_string()));
+        let req = test::TestRequest::default().to_http_request();
+
+        assert_eq!(extract_value(&req, "body", "challenge", &body_bytes, "Test").unwrap(), "42");
+        assert_eq!(extract_value(&req, "body", "verified", &body_bytes, "Test").unwrap(), "true");
+        assert_eq!(extract_value(&req, "body", "/entry/0/time", &body_bytes, "Test").unwrap(), "1746313253959");
+        assert_eq!(extract_value(&req, "body", "$.entry[0].id", &body_bytes, "Test").unwrap(), "578564948682799");
+
+        // Null, objects and arrays are rejected
+        assert!(extract_value(&req, "body", "nothing", &body_bytes, "Test").is_err());
+        assert!(extract_value(&req, "body", "entry", &body_bytes, "Test").is_err());
+        assert!(extract_value(&req, "body", "/entry/0", &body_bytes, "Test").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_form_extraction {
+    use super::extractors::*;
+
+    use actix_web::test;
+    use actix_web::http::header;
+    use bytes::Bytes;
+
+    const BOUNDARY: &str = "----pollhook7MA4YWxk";
+
+    fn multipart_body() -> Bytes {
+        Bytes::from(format!(
+            "--{b}\r\n\
+             Content-Disposition: form-data; name=\"token\"\r\n\r\n\
+             form-secret\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"recipient\"\r\n\r\n\
+             a@example.com\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"recipient\"\r\n\r\n\
+             b@example.com\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"attachment-1\"; filename=\"note.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             hello\r\n\
+             --{b}--\r\n",
+            b = BOUNDARY
+        ))
+    }
+
+    fn multipart_request() -> actix_web::HttpRequest {
+        test::TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", BOUNDARY)))
+            .to_http_request()
+    }
+
+    #[actix_web::test]
+    async fn test_extract_from_form() {
+        let body = Some(Bytes::from("token=abc%20def&challenge=12345"));
+
+        // Explicit form location, whatever the Content-Type
+        let req = test::TestRequest::post().to_http_request();
+        assert_eq!(extract_value(&req, "form", "token", &body, "Test").unwrap(), "abc def");
+        assert!(extract_value(&req, "form", "missing", &body, "Test").is_err());
+
+        // Body location picks the form parser from the Content-Type
+        let req = test::TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded; charset=utf-8"))
+            .to_http_request();
+        assert_eq!(extract_value(&req, "body", "challenge", &body, "Test").unwrap(), "12345");
+    }
+
+    #[actix_web::test]
+    async fn test_extract_from_multipart() {
+        let body = Some(multipart_body());
+        let req = multipart_request();
+
+        assert_eq!(extract_value(&req, "multipart", "token", &body, "Test").unwrap(), "form-secret");
+        assert_eq!(extract_value(&req, "body", "token", &body, "Test").unwrap(), "form-secret");
+        assert_eq!(extract_value(&req, "body", "/recipient/1", &body, "Test").unwrap(), "b@example.com");
+        assert_eq!(extract_value(&req, "body", "attachment-1::filename", &body, "Test").unwrap(), "note.txt");
+
+        // Multipart needs a boundary
+        let req = test::TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "multipart/form-data"))
+            .to_http_request();
+        assert!(extract_value(&req, "multipart", "token", &body, "Test").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_xml_extraction {
+    use super::extractors::*;
+    use super::template::TemplateContext;
+    use super::verification::build_response;
+    use crate::verification_handler::verification_config::ResponseConfig;
+
+    use actix_web::test;
+    use actix_web::http::{header, StatusCode};
+    use bytes::Bytes;
+    use serde_json::json;
+
+    const WECHAT_MESSAGE: &str = "<xml>\
+        <ToUserName><![CDATA[gh_toUser]]></ToUserName>\
+        <FromUserName><![CDATA[fromUser]]></FromUserName>\
+        <CreateTime>1348831860</CreateTime>\
+        <MsgType><![CDATA[text]]></MsgType>\
+        <Content><![CDATA[a < b & c]]></Content>\
+        <Item id=\"1\">first</Item><Item id=\"2\">second</Item>\
+        <Empty/>\
+        </xml>";
+
+    #[actix_web::test]
+    async fn test_extract_from_xml() {
+        let body = Some(Bytes::from(WECHAT_MESSAGE));
+        let req = test::TestRequest::post().to_http_request();
+
+        assert_eq!(extract_value(&req, "xml", "xml::ToUserName", &body, "Test").unwrap(), "gh_toUser");
+        assert_eq!(extract_value(&req, "xml", "/xml/CreateTime", &body, "Test").unwrap(), "1348831860");
+        assert_eq!(extract_value(&req, "xml", "xml::Content", &body, "Test").unwrap(), "a < b & c");
+        assert_eq!(extract_value(&req, "xml", "$.xml.Item[1]['#text']", &body, "Test").unwrap(), "second");
+        assert_eq!(extract_value(&req, "xml", "/xml/Item/0/@id", &body, "Test").unwrap(), "1");
+        assert_eq!(extract_value(&req, "xml", "xml::Empty", &body, "Test").unwrap(), "");
+        assert!(extract_value(&req, "xml", "xml::Missing", &body, "Test").is_err());
+
+        // Body location picks the XML parser from the Content-Type
+        let req = test::TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "text/xml; charset=utf-8"))
+            .to_http_request();
+        assert_eq!(extract_value(&req, "body", "xml::MsgType", &body, "Test").unwrap(), "text");
+
+        // Malformed XML
+        let body = Some(Bytes::from("<xml><a>1</b></xml>"));
+        assert!(extract_value(&req, "xml", "xml::a", &body, "Test").is_err());
+        let body = Some(Bytes::from("<xml><a>1</a>"));
+        assert!(extract_value(&req, "xml", "xml::a", &body, "Test").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_xml_response_content_type() {
+        let response_config: ResponseConfig = serde_json::from_value(json!({
+            "type": "application/xml",
+            "data": "<xml><Echo>@challenge</Echo></xml>"
+        })).unwrap();
+
+        let response = build_response(&response_config, TemplateContext::new("abc"), StatusCode::OK).unwrap();
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "application/xml");
+    }
+}
+
+#[cfg(test)]
+mod test_capture_pattern {
+    use super::extractors::*;
+    use crate::verification_handler::verification_config::{ChallengeConfig, TokenConfig};
+
+    use actix_web::test;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_pattern_captures_group() {
+        let token_config: TokenConfig = serde_json::from_value(json!({
+            "in": "header", "locate": "Authorization", "pattern": "^Bearer (.+)$"
+        })).unwrap();
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "Bearer abc.def"))
+            .to_http_request();
+        assert_eq!(extract_token(&req, &token_config, &None).unwrap(), "abc.def");
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "Basic abc"))
+            .to_http_request();
+        assert!(extract_token(&req, &token_config, &None).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_pattern_without_group_keeps_match() {
+        let challenge_config: ChallengeConfig = serde_json::from_value(json!({
+            "in": "body", "locate": "message", "pattern": "[0-9]{6}"
+        })).unwrap();
+
+        let req = test::TestRequest::post().to_http_request();
+        let body = Some(Bytes::from(json!({"message": "Your code is 482913."}).to_string()));
+        assert_eq!(extract_challenge(&req, &challenge_config, &body).unwrap(), "482913");
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_pattern_rejected_at_load() {
+        let result: Result<TokenConfig, _> = serde_yaml::from_str("in: query\nlocate: token\npattern: \"(unclosed\"\n");
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("Invalid pattern"), "{}", error);
+    }
+}
+
+#[cfg(test)]
+mod test_response_options {
+    use super::template::TemplateContext;
+    use super::verification::{build_response, verification_handler};
+    use crate::token_set::TokenSet;
+    use crate::verification_handler::verification_config::{ResponseConfig, VerificationConfig};
+
+    use actix_web::body::to_bytes;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, FromRequest, HttpResponse};
+    use bytes::Bytes;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_status_headers_and_mime_type() {
+        let response_config: ResponseConfig = serde_json::from_value(json!({
+            "type": "text/html; charset=utf-8",
+            "status": 202,
+            "data": "<p>@challenge</p>",
+            "headers": {
+                "X-Content-Type-Options": "nosniff",
+                "Cache-Control": "no-store",
+                "X-Echo": "@challenge"
+            }
+        })).unwrap();
+
+        let response = build_response(&response_config, TemplateContext::new("abc"), StatusCode::OK).unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "text/html; charset=utf-8");
+        assert_eq!(response.headers().get("x-content-type-options").unwrap(), "nosniff");
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "no-store");
+        assert_eq!(response.headers().get("x-echo").unwrap(), "abc");
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), Bytes::from("<p>abc</p>"));
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_status_and_type_rejected_at_load() {
+        let result: Result<ResponseConfig, _> = serde_json::from_value(json!({ "type": "text/plain", "status": 42 }));
+        assert!(result.unwrap_err().to_string().contains("Invalid status code"));
+
+        let result: Result<ResponseConfig, _> = serde_json::from_value(json!({ "type": "not a mime" }));
+        assert!(result.unwrap_err().to_string().contains("Invalid content type"));
+    }
+
+    async fn call(uri: &str, config: &VerificationConfig) -> HttpResponse {
+        let (req, mut payload) = test::TestRequest::get().uri(uri).to_http_parts();
+        let payload = web::Payload::from_request(&req, &mut payload).await.unwrap();
+        verification_handler(req, payload, config.clone()).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_failure_response() {
+        let mut config: VerificationConfig = serde_json::from_value(json!({
+            "path": "/callhook/dropbox",
+            "token": { "in": "query", "locate": "token" },
+            "challenge": { "in": "query", "locate": "challenge" },
+            "response": { "type": "text/plain", "data": "@challenge" },
+            "failure_response": {
+                "type": "application/json",
+                "data": r#"{"error":"invalid token"}"#,
+                "headers": { "X-Content-Type-Options": "nosniff" }
+            }
+        })).unwrap();
+        config.set_expected_tokens(TokenSet::parse("secret").unwrap());
+
+        // Wrong token, failure responses default to 403
+        let response = call("/callhook/dropbox?token=wrong&challenge=abc", &config).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.headers().get("x-content-type-options").unwrap(), "nosniff");
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), Bytes::from(r#"{"error":"invalid token"}"#));
+
+        // Missing token gets the same response
+        let response = call("/callhook/dropbox?challenge=abc", &config).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = call("/callhook/dropbox?token=secret&challenge=abc", &config).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), Bytes::from("abc"));
+    }
+}
+
+#[cfg(test)]
+mod test_request_templates {
+    use super::template::*;
+    use super::verification::build_response;
+    use crate::verification_handler::verification_config::ResponseConfig;
+
+    use actix_web::body::to_bytes;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test;
+    use bytes::Bytes;
+    use serde_json::{json, Value};
+
+    fn request() -> actix_web::HttpRequest {
+        test::TestRequest::post()
+            .uri("/callhook/acme/events?hub.challenge=abc&n=42")
+            .insert_header(("X-Request-Id", "req-1"))
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .to_http_request()
+    }
+
+    fn body() -> Bytes {
+        Bytes::from(json!({"data": {"id": "evt_1", "count": 3, "tags": ["a", "b"]}, "note": "say \"hi\" <b>"}).to_string())
+    }
+
+    async fn respond(response: Value) -> (StatusCode, String) {
+        let response_config: ResponseConfig = serde_json::from_value(response).unwrap();
+        let (req, body) = (request(), body());
+        let context = TemplateContext::new("chal").with_request(&req, Some(&body));
+
+        let response = build_response(&response_config, context, StatusCode::OK).unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_request_placeholders() {
+        let (req, body) = (request(), body());
+        let context = TemplateContext::new("chal").with_request(&req, Some(&body));
+        let render = |template: &str| render_template(template, &context).unwrap();
+
+        assert_eq!(render("@query.hub.challenge"), "abc");
+        assert_eq!(render("@header.X-Request-Id"), "req-1");
+        assert_eq!(render("@body./data/id,@body.$.data.count,@body.data::tags::1"), "evt_1,3,b");
+        assert_eq!(render("@path.2"), "acme");
+        assert_eq!(render("@{query.hub.challenge}.done"), "abc.done");
+        assert_eq!(render("@{body.$['data']['id']}"), "evt_1");
+
+        // Missing values render empty, bare prefixes are kept
+        assert_eq!(render("[@query.missing][@body./nope]"), "[][]");
+        assert_eq!(render("@query. and @body"), "@query. and @body");
+
+        assert!(chrono::DateTime::parse_from_rfc3339(&render("@now")).is_ok());
+        assert_eq!(render("@uuid").len(), 36);
+        assert_ne!(render("@uuid"), render("@uuid"));
+        assert!(render_template("@{nope}", &context).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_escaping_follows_content_type() {
+        let (_, json_body) = respond(json!({
+            "type": "application/json",
+            "data": r#"{"note":"@body.note"}"#
+        })).await;
+        assert_eq!(serde_json::from_str::<Value>(&json_body).unwrap(), json!({"note": "say \"hi\" <b>"}));
+
+        let (_, xml_body) = respond(json!({
+            "type": "application/xml",
+            "data": "<xml note=\"@body.note\">@body.note</xml>"
+        })).await;
+        assert_eq!(
+            xml_body,
+            "<xml note=\"say &quot;hi&quot; &lt;b&gt;\">say &quot;hi&quot; &lt;b&gt;</xml>"
+        );
+
+        let (_, text_body) = respond(json!({ "type": "text/plain", "data": "@body.note" })).await;
+        assert_eq!(text_body, "say \"hi\" <b>");
+    }
+
+    #[actix_web::test]
+    async fn test_structured_data_keeps_types() {
+        let (status, body) = respond(json!({
+            "type": "application/json",
+            "status": 201,
+            "data": {
+                "challenge": "@challenge",
+                "id": "@body./data/id",
+                "count": "@body./data/count",
+                "tags": "@body./data/tags",
+                "n": "@json(@query.n)",
+                "label": "event @body./data/id",
+                "fixed": [true, 7],
+                "nested": { "@query.hub.challenge": "@query.missing" }
+            }
+        })).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), json!({
+            "challenge": "chal",
+            "id": "evt_1",
+            "count": 3,
+            "tags": ["a", "b"],
+            "n": 42,
+            "label": "event evt_1",
+            "fixed": [true, 7],
+            "nested": { "abc": null }
+        }));
+
+        // in_path nests typed values too
+        let (_, body) = respond(json!({
+            "type": "application/json",
+            "in_path": "data::count",
+            "data": "@body./data/count"
+        })).await;
+        assert_eq!(body, r#"{"data":{"count":3}}"#);
+    }
+}
+
+#[cfg(test)]
+mod test_path_params {
+    use super::verification::verification_handler;
+    use crate::token_set::TokenSet;
+    use crate::verification_handler::verification_config::VerificationConfig;
+
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, FromRequest};
+    use bytes::Bytes;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn config(path: &str) -> VerificationConfig {
+        serde_json::from_value(json!({
+            "path": path,
+            "token": { "in": "param", "locate": "token" },
+            "challenge": { "in": "query", "locate": "challenge" },
+            "response": { "type": "text/plain", "data": "@param.tenant:@challenge" }
+        })).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_named_segments_are_captured() {
+        let config = config("/callhook/{tenant}/.../{token}");
+
+        let params = config.match_verification_path("/callhook/acme/v1/verify/s3cret").unwrap();
+        assert_eq!(params, BTreeMap::from([
+            ("tenant".to_string(), "acme".to_string()),
+            ("token".to_string(), "s3cret".to_string()),
+        ]));
+
+        // Each named segment takes exactly one segment
+        assert!(config.match_verification_path("/callhook/acme/s3cret").is_none());
+        assert!(config.match_verification_path("/callhook/acme/v1/verify/s3cret/").is_some());
+        assert!(!config.is_verification_path("/other/acme/v1/s3cret".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_param_location_and_placeholder() {
+        let mut config = config("/callhook/{tenant}/verify/{token}");
+        config.set_expected_tokens(TokenSet::parse("s3cret").unwrap());
+
+        let (req, mut payload) = test::TestRequest::get()
+            .uri("/callhook/acme/verify/s3cret?challenge=abc")
+            .to_http_parts();
+        let payload = web::Payload::from_request(&req, &mut payload).await.unwrap();
+
+        let response = verification_handler(req, payload, config).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), Bytes::from("acme:abc"));
+    }
+}
@@ -1,10 +1,16 @@
-use actix_web::{web, Error, HttpRequest, HttpResponse};
-use actix_web::http::header;
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
 use bytes::{Bytes, BytesMut};
 use log::{debug, error};
 use futures::StreamExt;
+use crate::lockout::AuthFailure;
 use crate::verification_handler::{extractors};
-use crate::verification_handler::verification_config::VerificationConfig;
+use crate::verification_handler::template::{
+    render_escaped, render_structured, render_template, render_value, Escape, TemplateContext,
+};
+use crate::verification_handler::verification_config::{ResponseConfig, ResponseData, VerificationConfig};
+use serde_json::Value;
 
 
 
@@ -14,10 +20,11 @@ pub async fn verification_handler(
     payload: web::Payload,
     config: VerificationConfig,
 ) -> Result<HttpResponse, Box<dyn std::error::Error>> {
-    // Check verification path
-    if !config.is_verification_path(req.path().to_string()) {
+    // Check verification path, keeping its named segments for `in: param`
+    let Some(params) = config.match_verification_path(req.path()) else {
         return Err("Invalid verification path".into());
-    }
+    };
+    req.extensions_mut().insert(extractors::PathParams(params));
 
     // Collect the payload
     let mut body = BytesMut::new();
@@ -47,59 +54,108 @@ async fn verify_from_config(
 
     debug!("Processing request: {} {}", method, path);
 
-    let request_token = extractors::extract_token(&req, config.get_token_config(), &body)?;
+    if let Some(jwt_config) = config.get_jwt_config()
+        && let Err(e) = jwt_config.verify_request(&req).await
+    {
+        req.extensions_mut().insert(AuthFailure("invalid bearer token"));
+        return match config.get_failure_response_config() {
+            Some(failure_response) => {
+                let context = TemplateContext::new("").with_request(&req, body.as_ref());
+                build_response(failure_response, context, StatusCode::UNAUTHORIZED)
+            }
+            None => Err(e),
+        };
+    }
 
-    if !config.is_token_valid(request_token) {
-        error!("Token verification failed");
-        return Ok(HttpResponse::Forbidden().finish());
+    if let Some(token_config) = config.get_token_config() {
+        let request_token = match extractors::extract_token(&req, token_config, &body) {
+            Ok(token) => token,
+            Err(e) => {
+                req.extensions_mut().insert(AuthFailure("missing token"));
+                match config.get_failure_response_config() {
+                    Some(failure_response) => {
+                        let context = TemplateContext::new("").with_request(&req, body.as_ref());
+                        return build_response(failure_response, context, StatusCode::FORBIDDEN);
+                    }
+                    None => return Err(e),
+                }
+            }
+        };
+
+        if !config.is_token_valid(request_token) {
+            error!("Token verification failed");
+            req.extensions_mut().insert(AuthFailure("invalid token"));
+            return match config.get_failure_response_config() {
+                Some(failure_response) => {
+                    let context = TemplateContext::new("").with_request(&req, body.as_ref());
+                    build_response(failure_response, context, StatusCode::FORBIDDEN)
+                }
+                None => Ok(HttpResponse::Forbidden().finish()),
+            };
+        }
     }
 
     // Extract challenge from request based on config
     let challenge = extractors::extract_challenge(&req, config.get_challenge_config(), &body)?;
 
-    let response_config = config.get_response_config();
-
-    // Prepare response based on config
-    let response_data = if let Some(in_path) = response_config.get_in_path() {
-        // Handle the case where data needs to be inserted at a specific path
-        let path_parts: Vec<&str> = in_path.split("::").collect();
-        let mut json_value = serde_json::Value::Object(serde_json::Map::new());
-
-        // Create nested structure
-        let mut current = &mut json_value;
-        for (i, part) in path_parts.iter().enumerate() {
-            if i < path_parts.len() - 1 {
-                // Create nested objects for path
-                if !current.as_object().unwrap().contains_key(*part) {
-                    current.as_object_mut().unwrap().insert(
-                        part.to_string(),
-                        serde_json::Value::Object(serde_json::Map::new()),
-                    );
-                }
-                current = current.as_object_mut().unwrap().get_mut(*part).unwrap();
-            } else {
-                // Insert the challenge at the final path
-                let value = response_config.get_data().replace("@challenge", &challenge);
-                current.as_object_mut().unwrap().insert(
-                    part.to_string(),
-                    serde_json::Value::String(value),
-                );
-            }
-        }
+    let context = TemplateContext::new(&challenge).with_request(&req, body.as_ref());
+    build_response(config.get_response_config(), context, StatusCode::OK)
+}
+
+/// Nest `value` under an `in_path` such as `data::challenge`
+fn nest_at_path(in_path: &str, value: Value) -> Value {
+    in_path.rsplit("::").fold(value, |inner, part| {
+        let mut object = serde_json::Map::new();
+        object.insert(part.to_string(), inner);
+        Value::Object(object)
+    })
+}
+
+/// Build a response from its config, using `default_status` when the config sets none
+pub fn build_response(
+    response_config: &ResponseConfig,
+    context: TemplateContext,
+    default_status: StatusCode,
+) -> Result<HttpResponse, Error> {
+    let context = &context.with_secrets(response_config.get_secrets());
+    let render_error = |e: String| {
+        error!("Failed to render response template: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to render response")
+    };
+    let content_type = response_config.get_content_type();
 
-        serde_json::to_string(&json_value).unwrap_or_else(|_| {
-            error!("Failed to serialize JSON response");
-            "{}".to_string()
-        })
-    } else {
-        // Simple string replacement
-        response_config.get_data().replace("@challenge", &challenge)
+    // Text templates are escaped for their content type; anything built as JSON is serialized instead
+    let response_data = match (response_config.get_data(), response_config.get_in_path()) {
+        (ResponseData::Text(template), None) => {
+            render_escaped(template, context, Escape::for_content_type(content_type.as_str())).map_err(render_error)?
+        }
+        (data, in_path) => {
+            let value = match data {
+                ResponseData::Text(template) => render_value(template, context),
+                ResponseData::Structured(data) => render_structured(data, context),
+            }.map_err(render_error)?;
+            let value = match in_path {
+                Some(in_path) => nest_at_path(&in_path, value),
+                None => value,
+            };
+            value.to_string()
+        }
     };
 
-    // Return appropriate response with proper content type
-    let mut response_builder = HttpResponse::Ok();
+    // Return appropriate response with proper status and content type, then any configured headers
+    let mut response_builder = HttpResponse::build(response_config.get_status().unwrap_or(default_status));
     response_builder.insert_header((header::CONTENT_TYPE, response_config.get_content_type().as_str()));
 
+    for (name, value) in response_config.get_headers() {
+        let value = render_template(value, context).map_err(render_error)?;
+        let header = HeaderName::from_bytes(name.as_bytes()).ok().zip(HeaderValue::from_str(&value).ok());
+        let Some(header) = header else {
+            error!("Invalid response header: {}: {}", name, value);
+            return Err(actix_web::error::ErrorInternalServerError("Failed to render response"));
+        };
+        response_builder.insert_header(header);
+    }
+
     Ok(response_builder.body(response_data))
 }
 
@@ -1,11 +1,53 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
 use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+use actix_web::http::StatusCode;
+use regex::Regex;
+use crate::jwt_auth::JwtConfig;
+use crate::path_pattern::PathPattern;
+use crate::source_ip::IpAllowlist;
+use crate::token_set::TokenSet;
+use crate::verification_handler::template::secret_envs;
+
+
+/// Regex applied to an extracted value, compiled when the config is loaded.
+/// The first capture group is kept, or the whole match when the pattern has no group.
+#[derive(Debug, Clone)]
+pub struct CapturePattern(Regex);
+
+// Custom deserialization for CapturePattern, so invalid regexes fail at config load
+impl<'de> Deserialize<'de> for CapturePattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Regex::new(&s)
+            .map(CapturePattern)
+            .map_err(|e| D::Error::custom(format!("Invalid pattern {}: {}", s, e)))
+    }
+}
 
+impl CapturePattern {
+    /// Take the captured part of `value`, or `None` when the pattern does not match
+    pub fn capture(&self, value: &str) -> Option<String> {
+        let captures = self.0.captures(value)?;
+        captures.get(1).or_else(|| captures.get(0)).map(|m| m.as_str().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TokenConfig {
     #[serde(rename = "in")]
     location: String,
     locate: String,
+    #[serde(default)]
+    pattern: Option<CapturePattern>,
 }
 
 impl TokenConfig {
@@ -16,6 +58,10 @@ impl TokenConfig {
     pub fn get_locate(&self) -> String {
         self.locate.clone()
     }
+
+    pub fn get_pattern(&self) -> Option<&CapturePattern> {
+        self.pattern.as_ref()
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -23,6 +69,8 @@ pub struct ChallengeConfig {
     #[serde(rename = "in")]
     location: String,
     locate: String,
+    #[serde(default)]
+    pattern: Option<CapturePattern>,
 }
 
 impl ChallengeConfig {
@@ -33,6 +81,10 @@ impl ChallengeConfig {
     pub fn get_locate(&self) -> String {
         self.locate.clone()
     }
+
+    pub fn get_pattern(&self) -> Option<&CapturePattern> {
+        self.pattern.as_ref()
+    }
 }
 
 // Define an enum for content types
@@ -40,6 +92,8 @@ impl ChallengeConfig {
 pub enum ContentType {
     TextPlain,
     ApplicationJson,
+    ApplicationXml,
+    Other(String), // Any other MIME type, e.g. text/html
 }
 
 // Custom deserialization for ContentType
@@ -49,11 +103,13 @@ impl<'de> Deserialize<'de> for ContentType {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        match s.as_str() {
+        match s.to_lowercase().as_str() {
             "text/plain" => Ok(ContentType::TextPlain),
             "application/json" => Ok(ContentType::ApplicationJson),
+            "application/xml" => Ok(ContentType::ApplicationXml),
+            _ if s.parse::<mime::Mime>().is_ok() => Ok(ContentType::Other(s)),
             _ => Err(serde::de::Error::custom(format!(
-                "Invalid content type: {}. Expected 'text/plain' or 'application/json'",
+                "Invalid content type: {}. Expected a MIME type such as 'text/plain' or 'application/json'",
                 s
             ))),
         }
@@ -62,75 +118,194 @@ impl<'de> Deserialize<'de> for ContentType {
 
 // Implementation to get string representation
 impl ContentType {
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             ContentType::TextPlain => "text/plain",
             ContentType::ApplicationJson => "application/json",
+            ContentType::ApplicationXml => "application/xml",
+            ContentType::Other(mime) => mime,
+        }
+    }
+}
+
+/// Response body: a template string, or structured data whose strings are templates
+#[derive(Debug, Clone)]
+pub enum ResponseData {
+    Text(String),
+    Structured(serde_json::Value), // Rendered to JSON, keeping typed values
+}
+
+impl Default for ResponseData {
+    fn default() -> Self {
+        ResponseData::Text(String::new())
+    }
+}
+
+// Custom deserialization for ResponseData, so YAML mappings and lists become structured data
+impl<'de> Deserialize<'de> for ResponseData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(text) => Ok(ResponseData::Text(text)),
+            value => Ok(ResponseData::Structured(value)),
         }
     }
 }
 
+// Custom deserialization for response status codes, so invalid codes fail at config load
+fn deserialize_status<'de, D>(deserializer: D) -> Result<Option<StatusCode>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let code = u16::deserialize(deserializer)?;
+    StatusCode::from_u16(code)
+        .map(Some)
+        .map_err(|_| D::Error::custom(format!("Invalid status code: {}. Expected 100-999", code)))
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct ResponseConfig {
     #[serde(rename = "type")]
     content_type: ContentType,
-    data: String,
+    #[serde(default)]
+    data: ResponseData,
     #[serde(default)]
     in_path: Option<String>, // Optional field for path in response data
+    #[serde(default, deserialize_with = "deserialize_status")]
+    status: Option<StatusCode>, // Defaults to 200, or 403 for failure responses
+    #[serde(default)]
+    headers: HashMap<String, String>, // Values are rendered like `data`
+    #[serde(skip)]
+    secrets: HashMap<String, String>, // Read from the env vars `@hmac_*` functions name
 }
 
 impl ResponseConfig {
+    pub fn get_status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    pub fn get_headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
     pub fn get_content_type(&self) -> ContentType {
         self.content_type.clone()
     }
 
-    pub fn get_data(&self) -> String {
-        self.data.clone()
+    pub fn get_data(&self) -> &ResponseData {
+        &self.data
     }
 
     pub fn get_in_path(&self) -> Option<String> {
         self.in_path.clone()
     }
+
+    pub fn get_secrets(&self) -> &HashMap<String, String> {
+        &self.secrets
+    }
+
+    /// Env vars named by `@hmac_*` functions in the data and header templates, sorted
+    pub fn secret_envs(&self) -> Vec<String> {
+        let mut templates: Vec<&str> = self.headers.values().map(String::as_str).collect();
+        match &self.data {
+            ResponseData::Text(template) => templates.push(template),
+            ResponseData::Structured(data) => collect_strings(data, &mut templates),
+        }
+
+        templates.into_iter().flat_map(secret_envs).collect::<BTreeSet<_>>().into_iter().collect()
+    }
+
+    /// Read the secret of every `@hmac_*` function, so a missing one stops startup
+    pub fn load_secrets(&mut self) -> Result<(), String> {
+        for secret_env in self.secret_envs() {
+            let secret = env::var(&secret_env).map_err(|_| format!("{} is not set", secret_env))?;
+            self.secrets.insert(secret_env, secret);
+        }
+        Ok(())
+    }
+}
+
+/// Every string in structured response data, keys included, as they are all rendered
+fn collect_strings<'a>(data: &'a serde_json::Value, strings: &mut Vec<&'a str>) {
+    match data {
+        serde_json::Value::String(text) => strings.push(text),
+        serde_json::Value::Array(items) => items.iter().for_each(|item| collect_strings(item, strings)),
+        serde_json::Value::Object(map) => map.iter().for_each(|(key, value)| {
+            strings.push(key);
+            collect_strings(value, strings);
+        }),
+        _ => {}
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct VerificationConfig {
-    path: String,
+    path: PathPattern,
     #[serde(default = "default_method")]
     method: String,
+    #[serde(default = "default_token_env")]
+    token_env: String,
     #[serde(skip)]
-    raw_token: String,
-    token: TokenConfig,
+    tokens: TokenSet,
+    #[serde(default)]
+    token: Option<TokenConfig>, // Shared token check, optional when `jwt` is set
+    #[serde(default)]
+    jwt: Option<JwtConfig>,
+    #[serde(default)]
+    allowed_ips: Option<IpAllowlist>, // Source addresses accepted, when set
     challenge: ChallengeConfig,
     response: ResponseConfig,
+    #[serde(default)]
+    failure_response: Option<ResponseConfig>, // Sent when the token is missing or wrong
 }
 
 fn default_method() -> String {
     actix_web::http::Method::GET.to_string()
 }
 
+fn default_token_env() -> String {
+    DEFAULT_TOKEN_ENV.to_string()
+}
+
+/// Env var holding the expected token when a block does not name its own
+pub const DEFAULT_TOKEN_ENV: &str = "VERIFY_TOKEN";
+
+/// Name given to a verification block written in the legacy single-block layout
+pub const DEFAULT_VERIFICATION_NAME: &str = "default";
+
 impl VerificationConfig {
-    #[allow(dead_code)]
     pub fn get_verification_path(&self) -> String {
-        self.path.clone()
+        self.path.as_str().to_string()
+    }
+
+    pub fn get_path_pattern(&self) -> &PathPattern {
+        &self.path
     }
 
     pub fn get_verification_method(&self) -> String {
         self.method.clone().to_uppercase()
     }
 
-    #[allow(dead_code)]
-    pub fn get_expected_token(&self) -> String {
-        self.raw_token.clone()
+    pub fn set_expected_tokens(&mut self, tokens: TokenSet) {
+        self.tokens = tokens;
+    }
+
+    pub fn get_token_env(&self) -> String {
+        self.token_env.clone()
     }
 
-    pub fn set_expected_token(&mut self, expected_token: String) {
-        self.raw_token = expected_token;
+    pub fn get_token_config(&self) -> Option<&TokenConfig> {
+        self.token.as_ref()
     }
 
+    pub fn get_jwt_config(&self) -> Option<&JwtConfig> {
+        self.jwt.as_ref()
+    }
 
-    pub fn get_token_config(&self) -> &TokenConfig {
-        &self.token
+    pub fn get_allowed_ips(&self) -> Option<&IpAllowlist> {
+        self.allowed_ips.as_ref()
     }
 
     pub fn get_challenge_config(&self) -> &ChallengeConfig {
@@ -141,115 +316,131 @@ impl VerificationConfig {
         &self.response
     }
 
+    pub fn get_failure_response_config(&self) -> Option<&ResponseConfig> {
+        self.failure_response.as_ref()
+    }
+
     pub fn is_token_valid(&self, token: String) -> bool {
-        self.raw_token.trim() == token.trim()
+        self.tokens.is_valid(&token)
     }
 
     pub fn is_verification_path_valid(&self) -> bool {
-        let verification_prefix = super::super::CALLBACK_PATH;
-        let verification_path = self.path.clone();
-        let config_segments: Vec<&str> = verification_path.split('/').collect();
-        if config_segments.len() == 0 {
-            return false;
-        }
-
-        // Handle various path formats
-        match config_segments.first() {
-            Some(&"") => {
-                // Path starts with slash, like "/verification/..."
-                config_segments.get(1).map_or(false, |&segment| segment == verification_prefix)
-            },
-            Some(&segment) => {
-                // Path doesn't start with slash, like "verification/..." or just "verification"
-                segment == verification_prefix
-            },
-            None => false,
-        }
+        // Verification paths live under the callback prefix, e.g. "/callhook/..."
+        self.path.first_literal() == Some(super::super::CALLBACK_PATH)
     }
 
     pub fn is_verification_path(&self, path_to_check: String) -> bool {
+        self.match_verification_path(&path_to_check).is_some()
+    }
+
+    /// Match a request path against the configured path, returning the named `{param}` segments
+    pub fn match_verification_path(&self, path_to_check: &str) -> Option<BTreeMap<String, String>> {
         // First check if base verification path is valid
         if !self.is_verification_path_valid() {
-            return false;
+            return None;
         }
 
-        let path_template = self.path.clone();
-
-        if path_template.contains("...") {
-            // Split both paths into segments and filter out empty segments
-            let config_segments: Vec<&str> = path_template.split('/')
-                .filter(|s| !s.is_empty())
-                .collect();
-            let request_segments: Vec<&str> = path_to_check.split('/')
-                .filter(|s| !s.is_empty())
-                .collect();
-
-            let mut request_idx = 0;
-            let mut config_idx = 0;
-
-            while config_idx < config_segments.len() && request_idx < request_segments.len() {
-                match config_segments[config_idx] {
-                    "..." => {
-                        // Wildcard must match at least one segment
-                        if config_idx == config_segments.len() - 1 {
-                            // Last segment is wildcard, must have at least one remaining request segment
-                            return request_segments.len() - request_idx >= 1;
-                        }
-
-                        // Find next non-wildcard segment in config
-                        let next_config_idx = config_idx + 1;
-                        if next_config_idx >= config_segments.len() {
-                            break;
-                        }
-
-                        let next_segment = config_segments[next_config_idx];
-                        if next_segment == "..." {
-                            // Next segment is also wildcard, just advance
-                            config_idx += 1;
-                            continue;
-                        }
-
-                        // Save the current request index before looking for next segment
-                        let saved_request_idx = request_idx;
-
-                        // Find next occurrence of next_segment in request
-                        while request_idx < request_segments.len() && request_segments[request_idx] != next_segment {
-                            request_idx += 1;
-                        }
-
-                        if request_idx >= request_segments.len() {
-                            return false; // Couldn't find next segment
-                        }
-
-                        // Ensure wildcard matched at least one segment
-                        if request_idx == saved_request_idx {
-                            return false; // Wildcard didn't match any segments
-                        }
-
-                        // Move past the wildcard in config
-                        config_idx += 1;
-                    },
-                    segment if segment == request_segments[request_idx] => {
-                        // Exact match
-                        request_idx += 1;
-                        config_idx += 1;
-                    },
-                    _ => {
-                        // No match
-                        return false;
-                    }
-                }
-            }
+        self.path.matches(path_to_check)
+    }
+}
+
+/// Named verification blocks, keyed the same way `DataMap` keys data endpoints by alias.
+///
+/// The legacy layout with a single block directly under `verification:` is still
+/// accepted and is registered under `DEFAULT_VERIFICATION_NAME`.
+#[derive(Clone, Debug)]
+pub struct VerificationMap {
+    blocks: HashMap<String, VerificationConfig>,
+    legacy_layout: bool, // The single block sits directly under `verification:`
+}
+
+impl<'de> Deserialize<'de> for VerificationMap {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+
+        // A single block carries its own `path` string, while in a map of blocks `path` could only name a block
+        if value.get("path").is_some_and(serde_yaml::Value::is_string) {
+            let config: VerificationConfig = serde_yaml::from_value(value).map_err(D::Error::custom)?;
+            return Ok(VerificationMap {
+                blocks: HashMap::from([(DEFAULT_VERIFICATION_NAME.to_string(), config)]),
+                legacy_layout: true,
+            });
+        }
+
+        let blocks: HashMap<String, VerificationConfig> = serde_yaml::from_value(value)
+            .map_err(D::Error::custom)?;
+        Ok(VerificationMap { blocks, legacy_layout: false })
+    }
+}
 
-            // Check if we've consumed all necessary segments
-            return config_idx >= config_segments.len() ||
-                config_segments[config_idx..].iter().all(|&s| s == "...");
+impl VerificationMap {
+    pub fn get(&self, name: &str) -> Option<&VerificationConfig> {
+        self.blocks.get(name)
+    }
+
+    /// Where a block sits in the YAML file, for error messages
+    pub fn get_yaml_location(&self, name: &str) -> String {
+        if self.legacy_layout {
+            "verification".to_string()
         } else {
-            // Simple exact matching for paths without wildcards
-            let norm_config = path_template.trim_end_matches('/').trim_start_matches('/');
-            let norm_path = path_to_check.trim_end_matches('/').trim_start_matches('/');
-            norm_config == norm_path
+            format!("verification.{}", name)
+        }
+    }
+
+    /// Name, method and config of every block, sorted by name so routes register in a stable order
+    pub fn get_name_method_config_vec(&self) -> Vec<(String, String, VerificationConfig)> {
+        let mut blocks: Vec<_> = self.blocks
+            .iter()
+            .map(|(name, config)| (name.clone(), config.get_verification_method(), config.clone()))
+            .collect();
+        blocks.sort_by(|a, b| a.0.cmp(&b.0));
+        blocks
+    }
+
+    /// Read the accepted tokens of every block from its env var or secrets file
+    pub fn load_expected_tokens(&mut self) -> Result<(), String> {
+        for (name, config) in self.blocks.iter_mut().filter(|(_, config)| config.token.is_some()) {
+            let tokens = TokenSet::from_env(&config.token_env)
+                .map_err(|e| format!("{} (expected token of verification block '{}')", e, name))?;
+            config.set_expected_tokens(tokens);
         }
+        Ok(())
     }
 
-}
\ No newline at end of file
+    /// Read the `@hmac_*` secrets of every block's responses
+    pub fn load_template_secrets(&mut self) -> Result<(), String> {
+        for (name, config) in self.blocks.iter_mut() {
+            config.response.load_secrets()
+                .map_err(|e| format!("{} (response of verification block '{}')", e, name))?;
+            if let Some(failure_response) = config.failure_response.as_mut() {
+                failure_response.load_secrets()
+                    .map_err(|e| format!("{} (failure_response of verification block '{}')", e, name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the JWKS file of every block that verifies bearer tokens
+    pub fn load_jwks(&mut self) -> Result<(), String> {
+        for (name, config) in self.blocks.iter_mut() {
+            if let Some(jwt) = config.jwt.as_mut() {
+                jwt.load_keys().map_err(|e| format!("{} (jwt of verification block '{}')", e, name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the allowlist file of every block that restricts source addresses
+    pub fn load_ip_allowlists(&mut self) -> Result<(), String> {
+        for (name, config) in self.blocks.iter_mut() {
+            if let Some(allowed_ips) = config.allowed_ips.as_mut() {
+                allowed_ips.load_ranges()
+                    .map_err(|e| format!("{} (allowed_ips of verification block '{}')", e, name))?;
+            }
+        }
+        Ok(())
+    }
+}
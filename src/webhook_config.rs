@@ -2,31 +2,57 @@ use serde::Deserialize;
 use crate::verification_handler::verification_config;
 use crate::data_handler::data_config;
 use crate::polling_handler::polling_config::PollingConfig;
+use crate::source_ip::TrustedProxies;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct WebhookConfig {
-    verification: verification_config::VerificationConfig,
+    verification: verification_config::VerificationMap,
     data: data_config::DataMap,
+    #[serde(default)]
+    trusted_proxies: TrustedProxies,
     #[serde(skip)]
     polling: PollingConfig,
 }
 
 impl WebhookConfig {
 
-    pub fn set_token(&mut self, token: String) {
-        self.verification.set_expected_token(token)
+    pub fn load_verification_tokens(&mut self) -> Result<(), String> {
+        self.verification.load_expected_tokens()
     }
 
-    pub fn get_verification_config(&self) -> &verification_config::VerificationConfig {
+    pub fn load_signature_secrets(&mut self) -> Result<(), String> {
+        self.data.load_signature_secrets()
+    }
+
+    pub fn load_template_secrets(&mut self) -> Result<(), String> {
+        self.verification.load_template_secrets()?;
+        self.data.load_template_secrets()
+    }
+
+    pub fn load_jwks(&mut self) -> Result<(), String> {
+        self.verification.load_jwks()?;
+        self.data.load_jwks()
+    }
+
+    pub fn load_ip_allowlists(&mut self) -> Result<(), String> {
+        self.verification.load_ip_allowlists()?;
+        self.data.load_ip_allowlists()
+    }
+
+    pub fn get_verification_map(&self) -> &verification_config::VerificationMap {
         &self.verification
     }
 
     pub fn get_data_config(&self) -> &data_config::DataMap {
         &self.data
     }
+
+    pub fn get_trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
+    }
     
-    pub fn get_verification_config_owned(&self) -> verification_config::VerificationConfig {
-        self.verification.clone()
+    pub fn get_verification_config_owned(&self, name: &str) -> Option<verification_config::VerificationConfig> {
+        self.verification.get(name).cloned()
     }
     
     pub fn get_polling_config_owned(&self) -> PollingConfig {
@@ -37,4 +63,3 @@ impl WebhookConfig {
         self.polling = PollingConfig::new();
     }
 }
-